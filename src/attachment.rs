@@ -0,0 +1,95 @@
+use macroquad::prelude::*;
+use super::{Space, insert_slot};
+
+
+#[derive(Clone, Copy, Debug)]
+pub struct Attachment {
+    pub particle: usize,
+    pub target: Vec2,
+    pub compliance: f32,
+    pub damping: f32,
+    pub max_force: f32,
+    lambda: f32,
+}
+
+
+impl Space {
+    // pulls `particle` towards `target` like a spring of the given stiffness whose force never exceeds `max_force`;
+    // the stiffness must be positive, infinite for a rigid attachment
+    pub fn add_attachment(&mut self, particle: usize, target: Vec2, stiffness: f32, max_force: f32) -> usize {
+        if particle >= self.n_objects {
+            panic!("Point out of range");
+        }
+        if stiffness.is_nan() || (stiffness <= 0.) {
+            panic!("Attachment stiffness must be positive, got {stiffness}");
+        }
+        let attachment = Attachment {
+            particle, target, max_force,
+            compliance: 1. / stiffness,
+            damping: 0.,
+            lambda: 0.,
+        };
+        insert_slot(&mut self.attachments, attachment)
+    }
+    pub fn remove_attachment(&mut self, handle: usize) {
+        if let Some(slot) = self.attachments.get_mut(handle) {
+            *slot = None;
+        }
+    }
+    pub fn attachment(&self, handle: usize) -> Option<&Attachment> {
+        self.attachments.get(handle).and_then(|slot| slot.as_ref())
+    }
+    pub fn set_attachment_target(&mut self, handle: usize, target: Vec2) {
+        if let Some(Some(attachment)) = self.attachments.get_mut(handle) {
+            attachment.target = target;
+        }
+    }
+    pub fn set_attachment_damping(&mut self, handle: usize, damping: f32) {
+        if let Some(Some(attachment)) = self.attachments.get_mut(handle) {
+            attachment.damping = damping;
+        }
+    }
+
+    pub(super) fn reset_attachments(&mut self) {
+        for attachment in self.attachments.iter_mut().flatten() {
+            attachment.lambda = 0.;
+        }
+    }
+    // one xpbd iteration, run interleaved with the link iterations
+    pub(super) fn solve_attachments(&mut self, sub_dt: f32) {
+        for handle in 0..self.attachments.len() {
+            let Some(mut attachment) = self.attachments[handle] else {
+                continue;
+            };
+            let axis = self.positions[attachment.particle] - attachment.target;
+            let dist = axis.length();
+            if dist == 0. {
+                continue;
+            }
+            let n = axis / dist;
+            let w = 1. / self.masses[attachment.particle];
+            if w == 0. {
+                continue;
+            }
+            let alpha = attachment.compliance / (sub_dt * sub_dt);
+            let gamma = attachment.compliance * attachment.damping / sub_dt;
+            let c_dot = n.dot(self.substep_displacement(attachment.particle, sub_dt));
+            let max_lambda = attachment.max_force * sub_dt * sub_dt;
+            let d_lambda = (-dist - alpha * attachment.lambda - gamma * c_dot) / ((1. + gamma) * w + alpha);
+            let lambda = (attachment.lambda + d_lambda).max(-max_lambda);
+            self.positions[attachment.particle] += w * (lambda - attachment.lambda) * n;
+            attachment.lambda = lambda;
+            self.attachments[handle] = Some(attachment);
+        }
+    }
+    pub(super) fn remap_particle_attachments(&mut self, remap: &[Option<usize>]) {
+        for slot in self.attachments.iter_mut() {
+            if let Some(attachment) = slot {
+                match remap[attachment.particle] {
+                    Some(particle) => attachment.particle = particle,
+                    None => *slot = None,
+                }
+            }
+        }
+    }
+}
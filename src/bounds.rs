@@ -0,0 +1,144 @@
+use macroquad::prelude::*;
+use std::collections::HashMap;
+use super::{Space, ParticleRemoval, RemovalCause};
+
+
+// what happens to particles whose center leaves the world
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutOfBounds {
+    #[default]
+    Delete,
+    Clamp,
+    Wrap,
+    Keep,
+}
+
+
+// shortest offset between two points when the world repeats every `periods` along the axes where it is nonzero
+pub(super) fn minimum_image(delta: Vec2, periods: Vec2) -> Vec2 {
+    let wrap = |d: f32, period: f32| if period > 0. { d - period * (d / period).round() } else { d };
+    vec2(wrap(delta.x, periods.x), wrap(delta.y, periods.y))
+}
+
+
+impl Space {
+    pub fn set_out_of_bounds(&mut self, policy: OutOfBounds) {
+        self.out_of_bounds = policy;
+    }
+    pub fn get_out_of_bounds(&self) -> OutOfBounds {
+        self.out_of_bounds
+    }
+    // periodic axes wrap around whatever the out-of-bounds policy, which is left to handle the other axes
+    pub fn set_periodic(&mut self, x: bool, y: bool) {
+        self.periodic = BVec2::new(x, y);
+    }
+    pub fn get_periodic(&self) -> BVec2 {
+        match self.out_of_bounds {
+            OutOfBounds::Wrap => BVec2::new(true, true),
+            _ => self.periodic,
+        }
+    }
+    pub fn particle_removals(&self) -> &[ParticleRemoval] {
+        &self.events.removals
+    }
+    pub fn drain_particle_removals(&mut self) -> Vec<ParticleRemoval> {
+        std::mem::take(&mut self.events.removals)
+    }
+    // the world spans [0, size) on both axes, the same area the grid covers
+    pub fn world_size(&self) -> Vec2 {
+        vec2(self.grid.width as f32, self.grid.height as f32) * self.grid.cellsize
+    }
+    // world size along the periodic axes, zero along the others
+    pub(super) fn periods(&self) -> Vec2 {
+        Vec2::select(self.get_periodic(), self.world_size(), vec2(0., 0.))
+    }
+    fn is_outside(&self, handle: usize) -> bool {
+        let size = self.world_size();
+        let pos = self.positions[handle];
+        (pos.x < 0.) || (pos.x >= size.x) || (pos.y < 0.) || (pos.y >= size.y)
+    }
+
+    pub(super) fn apply_bounds(&mut self) {
+        let periods = self.periods();
+        if periods != vec2(0., 0.) {
+            self.wrap_positions(periods);
+        }
+        match self.out_of_bounds {
+            OutOfBounds::Delete => self.remove_outside(),
+            OutOfBounds::Clamp => {
+                let size = self.world_size();
+                let periodic = self.get_periodic();
+                for (pos, radius) in self.positions.iter_mut().zip(self.radii.iter()) {
+                    let margin = Vec2::splat(radius.min(0.5 * size.min_element()));
+                    *pos = Vec2::select(periodic, *pos, pos.clamp(margin, size - margin));
+                }
+            },
+            OutOfBounds::Wrap | OutOfBounds::Keep => (),
+        }
+    }
+    fn wrap_positions(&mut self, periods: Vec2) {
+        // rem_euclid rounds tiny negative values up to exactly `period`, which is already the other side of the seam
+        let wrap = |x: f32, period: f32| match x.rem_euclid(period) {
+            _ if period <= 0. => x,
+            wrapped if wrapped >= period => 0.,
+            wrapped => wrapped,
+        };
+        for i in 0..self.n_objects {
+            let pos = self.positions[i];
+            let shift = vec2(wrap(pos.x, periods.x), wrap(pos.y, periods.y)) - pos;
+            if shift == vec2(0., 0.) {
+                continue;
+            }
+            // the older positions move along so the velocity and the interpolated drawing are unaffected
+            self.positions[i] += shift;
+            self.positions_old[i] += shift;
+            self.positions_prev[i] += shift;
+        }
+    }
+    pub fn remove_outside(&mut self) {
+        let outside: Vec<usize> = (0..self.n_objects).filter(|&i| self.is_outside(i)).collect();
+        if outside.is_empty() {
+            return;
+        }
+        for &particle in outside.iter() {
+            self.events.removals.push(ParticleRemoval { particle, position: self.positions[particle], cause: RemovalCause::OutOfBounds });
+        }
+        self.remove_particles(&outside);
+    }
+
+    // with `Keep` the grid clamps far away particles into its border cells; pairs involving them are found
+    // through a hash of the unclamped cells instead, so they cost no more than the ones inside the world
+    pub(super) fn outside_flags(&self) -> Vec<bool> {
+        if self.out_of_bounds != OutOfBounds::Keep {
+            return Vec::new();
+        }
+        let flags: Vec<bool> = (0..self.n_objects).map(|i| self.is_outside(i)).collect();
+        if flags.contains(&true) { flags } else { Vec::new() }
+    }
+    pub(super) fn apply_unbounded_collisions(&mut self, outside: &[bool], sub_dt: f32) {
+        if outside.is_empty() {
+            return;
+        }
+        let cellsize = self.grid.cellsize;
+        let cell_of = |pos: Vec2| ((pos.x / cellsize).floor() as i64, (pos.y / cellsize).floor() as i64);
+        let mut cells: HashMap<(i64, i64), Vec<usize>> = HashMap::new();
+        for (i, pos) in self.positions.iter().enumerate() {
+            cells.entry(cell_of(*pos)).or_default().push(i);
+        }
+        for i in (0..self.n_objects).filter(|&i| outside[i]) {
+            let (x, y) = cell_of(self.positions[i]);
+            for (dx, dy) in itertools::iproduct!(-1..=1, -1..=1) {
+                let Some(other) = cells.get(&(x + dx, y + dy)) else {
+                    continue;
+                };
+                for &j in other.iter() {
+                    // pairs of two outside particles are handled once, pairs inside the world by the grid
+                    if (j == i) || (outside[j] && (j < i)) {
+                        continue;
+                    }
+                    self.collide_pair(i, j, sub_dt);
+                }
+            }
+        }
+    }
+}
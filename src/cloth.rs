@@ -0,0 +1,113 @@
+use macroquad::prelude::*;
+use super::Space;
+
+
+const SHEAR_COMPLIANCE: f32 = 0.001;
+const BEND_COMPLIANCE: f32 = 0.01;
+
+
+// links are identified by the particle pair they join, like `link_exists`
+#[derive(Clone, Debug)]
+pub struct Rope {
+    pub particles: Vec<usize>,
+    pub links: Vec<(usize, usize)>,
+}
+
+// particles are stored row by row, starting at the top left corner
+#[derive(Clone, Debug)]
+pub struct Cloth {
+    pub particles: Vec<usize>,
+    pub columns: usize,
+    pub rows: usize,
+    pub structural: Vec<(usize, usize)>,
+    pub shear: Vec<(usize, usize)>,
+    pub bend: Vec<(usize, usize)>,
+}
+
+impl Cloth {
+    pub fn particle(&self, column: usize, row: usize) -> usize {
+        self.particles[row * self.columns + column]
+    }
+}
+
+
+impl Space {
+    // chain of `segments` rigid links from `start` to `end`
+    pub fn add_rope(&mut self, start: Vec2, end: Vec2, segments: usize, radius: f32) -> Rope {
+        let segments = segments.max(1);
+        let particles: Vec<usize> = (0..=segments)
+            .map(|i| self.add_particle(start.lerp(end, i as f32 / segments as f32), radius))
+            .collect();
+        let mut links = Vec::new();
+        for pair in particles.windows(2) {
+            self.add_link(pair[0], pair[1], f32::INFINITY);
+            links.push((pair[0], pair[1]));
+        }
+        Rope { particles, links }
+    }
+    // grid of `columns` x `rows` particles spanning [min, max], with rigid structural links and softer shear and bend links
+    pub fn add_cloth(&mut self, min: Vec2, max: Vec2, columns: usize, rows: usize, radius: f32) -> Cloth {
+        let (columns, rows) = (columns.max(2), rows.max(2));
+        let mut particles = Vec::new();
+        for row in 0..rows {
+            for column in 0..columns {
+                let t = vec2(column as f32 / (columns - 1) as f32, row as f32 / (rows - 1) as f32);
+                particles.push(self.add_particle(min + (max - min) * t, radius));
+            }
+        }
+        let mut cloth = Cloth { particles, columns, rows, structural: Vec::new(), shear: Vec::new(), bend: Vec::new() };
+        for row in 0..rows {
+            for column in 0..columns {
+                let p = cloth.particle(column, row);
+                if column + 1 < columns {
+                    cloth.structural.push((p, cloth.particle(column + 1, row)));
+                }
+                if row + 1 < rows {
+                    cloth.structural.push((p, cloth.particle(column, row + 1)));
+                }
+                if (column + 1 < columns) && (row + 1 < rows) {
+                    cloth.shear.push((p, cloth.particle(column + 1, row + 1)));
+                    cloth.shear.push((cloth.particle(column + 1, row), cloth.particle(column, row + 1)));
+                }
+                if column + 2 < columns {
+                    cloth.bend.push((p, cloth.particle(column + 2, row)));
+                }
+                if row + 2 < rows {
+                    cloth.bend.push((p, cloth.particle(column, row + 2)));
+                }
+            }
+        }
+        for &(p1, p2) in cloth.structural.iter() {
+            self.add_link(p1, p2, f32::INFINITY);
+        }
+        for &(p1, p2) in cloth.shear.iter() {
+            self.add_spring(p1, p2, SHEAR_COMPLIANCE, 0., f32::INFINITY);
+        }
+        for &(p1, p2) in cloth.bend.iter() {
+            self.add_spring(p1, p2, BEND_COMPLIANCE, 0., f32::INFINITY);
+        }
+        cloth
+    }
+    // rigid attachment holding a particle where it is, returned as an attachment handle
+    pub fn pin(&mut self, particle: usize) -> usize {
+        self.add_attachment(particle, self.positions[particle], f32::INFINITY, f32::INFINITY)
+    }
+
+    // keeps consecutive particles, such as neighbouring rope segments, from colliding with each other
+    pub fn disable_adjacent_collisions(&mut self, particles: &[usize]) {
+        for pair in particles.windows(2) {
+            self.ignore_collision(pair[0], pair[1]);
+        }
+    }
+    pub fn ignore_collision(&mut self, p1: usize, p2: usize) {
+        self.ignored_pairs.insert((p1.min(p2), p1.max(p2)));
+    }
+    pub(super) fn remap_particle_ignored_pairs(&mut self, remap: &[Option<usize>]) {
+        if self.ignored_pairs.is_empty() {
+            return;
+        }
+        self.ignored_pairs = self.ignored_pairs.iter()
+            .filter_map(|&(p1, p2)| Some((remap[p1]?, remap[p2]?)))
+            .collect();
+    }
+}
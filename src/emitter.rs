@@ -0,0 +1,142 @@
+use macroquad::prelude::*;
+use ::rand::{rngs::StdRng, Rng, SeedableRng};
+use std::ops::Range;
+use super::{Space, insert_slot};
+
+
+// particles leave `position` at `rate` per second plus `burst` at once on the first update, heading within
+// `spread` radians of `direction`; colours cycle through `gradient` once every `gradient_period` seconds.
+// emitted particles expire after `particle_lifetime` seconds, following `age_curve` meanwhile
+#[derive(Clone, Debug)]
+pub struct EmitterParams {
+    pub position: Vec2,
+    pub direction: Vec2,
+    pub spread: f32,
+    pub rate: f32,
+    pub burst: usize,
+    pub speed: Range<f32>,
+    pub radius: Range<f32>,
+    pub gradient: Vec<Color>,
+    pub gradient_period: f32,
+    pub lifetime: Option<f32>,
+    pub max_count: Option<usize>,
+    pub particle_lifetime: Option<Range<f32>>,
+    pub age_curve: Option<usize>,
+    pub seed: u64,
+}
+
+impl Default for EmitterParams {
+    fn default() -> Self {
+        Self {
+            position: vec2(50., 50.),
+            direction: vec2(0., 1.),
+            spread: 0.,
+            rate: 60.,
+            burst: 0,
+            speed: 10.0..10.0,
+            radius: 0.5..0.5,
+            gradient: vec![WHITE],
+            gradient_period: 1.,
+            lifetime: None,
+            max_count: None,
+            particle_lifetime: None,
+            age_curve: None,
+            seed: 0,
+        }
+    }
+}
+
+
+pub(super) struct EmitterSlot {
+    params: EmitterParams,
+    rng: StdRng,
+    time: f32,
+    accumulator: f32,
+    emitted: usize,
+}
+
+
+// evenly spaced colour stops, `t` in [0, 1] going from the first to the last
+pub(super) fn sample_gradient(gradient: &[Color], t: f32) -> Color {
+    match gradient.len() {
+        0 => WHITE,
+        1 => gradient[0],
+        n => {
+            let x = t.clamp(0., 1.) * (n - 1) as f32;
+            let k = (x.floor() as usize).min(n - 2);
+            let (a, b, f) = (gradient[k], gradient[k + 1], x - k as f32);
+            Color::new(a.r + (b.r - a.r) * f, a.g + (b.g - a.g) * f, a.b + (b.b - a.b) * f, a.a + (b.a - a.a) * f)
+        },
+    }
+}
+
+// ranges may be empty (start == end) to ask for a fixed value
+fn sample_range(rng: &mut StdRng, range: &Range<f32>) -> f32 {
+    if range.end > range.start { rng.gen_range(range.clone()) } else { range.start }
+}
+
+
+impl Space {
+    pub fn add_emitter(&mut self, params: EmitterParams) -> usize {
+        // the burst is owed from the start and goes out with the first update
+        let accumulator = params.burst as f32;
+        let emitter = EmitterSlot { rng: StdRng::seed_from_u64(params.seed), params, time: 0., accumulator, emitted: 0 };
+        insert_slot(&mut self.emitters, emitter)
+    }
+    pub fn remove_emitter(&mut self, handle: usize) {
+        if let Some(slot) = self.emitters.get_mut(handle) {
+            *slot = None;
+        }
+    }
+    pub fn emitter(&self, handle: usize) -> Option<&EmitterParams> {
+        self.emitters.get(handle).and_then(|slot| slot.as_ref()).map(|emitter| &emitter.params)
+    }
+    pub fn emitter_mut(&mut self, handle: usize) -> Option<&mut EmitterParams> {
+        self.emitters.get_mut(handle).and_then(|slot| slot.as_mut()).map(|emitter| &mut emitter.params)
+    }
+    // number of particles the emitter has created so far, 0 once it is gone
+    pub fn emitted(&self, handle: usize) -> usize {
+        self.emitters.get(handle).and_then(|slot| slot.as_ref()).map_or(0, |emitter| emitter.emitted)
+    }
+
+    // emitters past their lifetime remove themselves; one that reached its max count stays until removed
+    pub(super) fn apply_emitters(&mut self, dt: f32) {
+        for handle in 0..self.emitters.len() {
+            let Some(mut emitter) = self.emitters[handle].take() else {
+                continue;
+            };
+            if emitter.params.lifetime.is_some_and(|lifetime| emitter.time >= lifetime) {
+                continue;
+            }
+            let count = emitter.params.rate.max(0.) * dt + emitter.accumulator;
+            emitter.accumulator = count.fract();
+            let mut count = count as usize;
+            if let Some(max_count) = emitter.params.max_count {
+                count = count.min(max_count.saturating_sub(emitter.emitted));
+            }
+            // spread the particles over the update as if they had left one at a time
+            for k in 0..count {
+                self.emit(&mut emitter, dt * (count - 1 - k) as f32 / count as f32);
+            }
+            emitter.time += dt;
+            self.emitters[handle] = Some(emitter);
+        }
+    }
+    fn emit(&mut self, emitter: &mut EmitterSlot, age: f32) {
+        let params = &emitter.params;
+        let angle = if params.spread > 0. { emitter.rng.gen_range(-params.spread..=params.spread) } else { 0. };
+        let direction = Vec2::from_angle(angle).rotate(params.direction.normalize_or_zero());
+        let speed = sample_range(&mut emitter.rng, &params.speed);
+        let radius = sample_range(&mut emitter.rng, &params.radius);
+        let lifetime = params.particle_lifetime.as_ref().map(|lifetime| sample_range(&mut emitter.rng, lifetime));
+        let t = if params.gradient_period > 0. { (emitter.time / params.gradient_period).fract() } else { 0. };
+
+        let handle = self.add_particle(params.position + direction * speed * age, radius);
+        self.set_velocity(handle, direction * speed);
+        self.set_color(handle, sample_gradient(&params.gradient, t));
+        self.set_lifetime(handle, lifetime);
+        self.set_age_curve(handle, params.age_curve);
+        self.ages[handle] = age;
+        emitter.emitted += 1;
+    }
+}
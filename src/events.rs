@@ -0,0 +1,196 @@
+use macroquad::prelude::*;
+use std::collections::BTreeMap;
+use super::{Space, RegionEvent, UserData};
+
+
+#[derive(Clone, Copy, Debug)]
+pub struct LinkBreak {
+    pub p1: usize,
+    pub p2: usize,
+    pub strain: f32,
+    pub position: Vec2,
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RemovalCause {
+    OutOfBounds,
+    KillZone(usize),
+    Expired,
+    Reaction(usize),
+}
+
+// `particle` is the handle the particle had right before it was removed; unlike the other events, whose handles
+// follow the particles removed later in the same frame, it stays in the numbering of its own removal
+#[derive(Clone, Copy, Debug)]
+pub struct ParticleRemoval {
+    pub particle: usize,
+    pub position: Vec2,
+    pub cause: RemovalCause,
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ContactTarget {
+    Particle(usize),
+    Constraint(usize),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ContactPhase {
+    Begin,
+    Persist,
+    End,
+}
+
+// for particle pairs `particle` is always the lower handle; the normal points from `other` towards `particle`
+#[derive(Clone, Copy, Debug)]
+pub struct Contact {
+    pub particle: usize,
+    pub other: ContactTarget,
+    pub phase: ContactPhase,
+    pub normal: Vec2,
+    pub depth: f32,
+    pub impulse: f32,
+}
+
+
+#[derive(Default)]
+pub struct Events {
+    pub link_breaks: Vec<LinkBreak>,
+    pub total_link_breaks: usize,
+    pub contacts: Vec<Contact>,
+    pub region_events: Vec<RegionEvent>,
+    pub removals: Vec<ParticleRemoval>,
+    pub removed_user_data: Vec<(usize, UserData)>,
+
+    touching: BTreeMap<(usize, ContactTarget), Contact>,
+    active: BTreeMap<(usize, ContactTarget), Contact>,
+}
+
+impl Events {
+    pub fn clear(&mut self) {
+        self.link_breaks.clear();
+        self.contacts.clear();
+        self.region_events.clear();
+        self.removals.clear();
+        self.removed_user_data.clear();
+    }
+    pub fn record_contact(&mut self, particle: usize, other: ContactTarget, normal: Vec2, depth: f32, impulse: f32) {
+        let (particle, other, normal) = match other {
+            ContactTarget::Particle(j) if j < particle => (j, ContactTarget::Particle(particle), -normal),
+            _ => (particle, other, normal),
+        };
+        match self.touching.get_mut(&(particle, other)) {
+            Some(contact) => {
+                if depth > contact.depth {
+                    contact.normal = normal;
+                    contact.depth = depth;
+                }
+                contact.impulse += impulse;
+            },
+            None => {
+                self.touching.insert((particle, other), Contact { particle, other, phase: ContactPhase::Begin, normal, depth, impulse });
+            },
+        }
+    }
+    // compares the contacts touched during the last update with the ones active before it
+    pub fn finish_contacts(&mut self) {
+        let mut touching = std::mem::take(&mut self.touching);
+        for (key, contact) in self.active.iter() {
+            if !touching.contains_key(key) {
+                self.contacts.push(Contact { phase: ContactPhase::End, ..*contact });
+            }
+        }
+        for (key, contact) in touching.iter_mut() {
+            if self.active.contains_key(key) {
+                contact.phase = ContactPhase::Persist;
+            }
+            self.contacts.push(*contact);
+        }
+        self.active = touching;
+    }
+    // events and contacts of removed particles are dropped and the other handles are renumbered, so that
+    // events from earlier steps of a frame still point at the right particles
+    pub fn remap_particles(&mut self, remap: &[Option<usize>]) {
+        self.link_breaks.retain_mut(|link_break| {
+            let (Some(p1), Some(p2)) = (remap[link_break.p1], remap[link_break.p2]) else {
+                return false;
+            };
+            (link_break.p1, link_break.p2) = (p1, p2);
+            true
+        });
+        self.contacts.retain_mut(|contact| {
+            let other = match contact.other {
+                ContactTarget::Particle(j) => remap[j].map(ContactTarget::Particle),
+                other => Some(other),
+            };
+            let (Some(particle), Some(other)) = (remap[contact.particle], other) else {
+                return false;
+            };
+            (contact.particle, contact.other) = (particle, other);
+            true
+        });
+        self.region_events.retain_mut(|event| {
+            let Some(particle) = remap[event.particle] else {
+                return false;
+            };
+            event.particle = particle;
+            true
+        });
+        for contacts in [&mut self.touching, &mut self.active] {
+            *contacts = std::mem::take(contacts).into_iter()
+                .filter_map(|((particle, other), mut contact)| {
+                    let other = match other {
+                        ContactTarget::Particle(j) => ContactTarget::Particle(remap[j]?),
+                        _ => other,
+                    };
+                    contact.particle = remap[particle]?;
+                    contact.other = other;
+                    Some(((contact.particle, other), contact))
+                })
+                .collect();
+        }
+    }
+    pub fn clear_contacts(&mut self) {
+        self.touching.clear();
+        self.active.clear();
+    }
+}
+
+
+impl Space {
+    // events are collected over one call to `update` or `step` and cleared at the start of the next
+    pub fn link_breaks(&self) -> &[LinkBreak] {
+        &self.events.link_breaks
+    }
+    pub fn drain_link_breaks(&mut self) -> Vec<LinkBreak> {
+        std::mem::take(&mut self.events.link_breaks)
+    }
+    pub fn n_broken_links(&self) -> usize {
+        self.events.link_breaks.len()
+    }
+    pub fn total_broken_links(&self) -> usize {
+        self.events.total_link_breaks
+    }
+    pub fn contacts(&self) -> &[Contact] {
+        &self.events.contacts
+    }
+    pub fn drain_contacts(&mut self) -> Vec<Contact> {
+        std::mem::take(&mut self.events.contacts)
+    }
+
+    pub fn is_touching(&self, handle: usize, other: ContactTarget) -> bool {
+        let key = match other {
+            ContactTarget::Particle(j) if j < handle => (j, ContactTarget::Particle(handle)),
+            _ => (handle, other),
+        };
+        self.events.active.contains_key(&key)
+    }
+    pub fn touching(&self, handle: usize) -> Vec<Contact> {
+        self.events.active.values()
+            .filter(|contact| (contact.particle == handle) || (contact.other == ContactTarget::Particle(handle)))
+            .copied()
+            .collect()
+    }
+}
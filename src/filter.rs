@@ -0,0 +1,59 @@
+use super::Space;
+
+
+// two particles collide when each one's layer is in the other's mask and they do not share a nonzero group;
+// sensors report contacts without pushing or being pushed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CollisionFilter {
+    pub layer: u32,
+    pub mask: u32,
+    pub group: usize,
+    pub sensor: bool,
+}
+
+impl CollisionFilter {
+    pub fn collides_with(&self, other: &CollisionFilter) -> bool {
+        (self.layer & other.mask != 0) && (other.layer & self.mask != 0) && ((self.group == 0) || (self.group != other.group))
+    }
+}
+
+impl Default for CollisionFilter {
+    fn default() -> Self {
+        Self {
+            layer: 1,
+            mask: u32::MAX,
+            group: 0,
+            sensor: false,
+        }
+    }
+}
+
+
+impl Space {
+    pub fn set_collision_filter(&mut self, handle: usize, layer: u32, mask: u32) {
+        self.filters[handle].layer = layer;
+        self.filters[handle].mask = mask;
+    }
+    pub fn get_collision_filter(&self, handle: usize) -> CollisionFilter {
+        self.filters[handle]
+    }
+    // puts the particles in a fresh group whose members never collide with each other
+    pub fn add_collision_group(&mut self, particles: &[usize]) -> usize {
+        self.n_collision_groups += 1;
+        for &handle in particles {
+            self.filters[handle].group = self.n_collision_groups;
+        }
+        self.n_collision_groups
+    }
+    pub fn set_sensor(&mut self, handle: usize, sensor: bool) {
+        self.filters[handle].sensor = sensor;
+    }
+    // a ghost collides with nothing, constraints included
+    pub fn set_ghost(&mut self, handle: usize) {
+        self.set_collision_filter(handle, 0, 0);
+    }
+    // constraints only act on particles whose layer is in their mask
+    pub fn set_constraint_mask(&mut self, constraint: usize, mask: u32) {
+        self.constraint_masks[constraint] = mask;
+    }
+}
@@ -0,0 +1,162 @@
+use macroquad::prelude::*;
+use std::f32::consts::PI;
+use super::{Space, minimum_image};
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParticleKind {
+    Solid,
+    Fluid,
+}
+
+
+// position based fluids (macklin & müller 2013)
+#[derive(Clone, Copy, Debug)]
+pub struct FluidParams {
+    pub kernel_radius: f32,
+    pub rest_density: f32,
+    pub iterations: usize,
+    pub relaxation: f32,
+    pub viscosity: f32,
+    pub surface_tension: f32,
+}
+
+impl FluidParams {
+    // rest density of fluid particles packed on a square lattice of the given spacing
+    pub fn for_spacing(spacing: f32, kernel_radius: f32) -> Self {
+        let reach = (kernel_radius / spacing).ceil() as isize;
+        let mut rest_density = 0.;
+        for i in -reach..=reach {
+            for j in -reach..=reach {
+                rest_density += poly6(vec2(i as f32, j as f32).length() * spacing, kernel_radius);
+            }
+        }
+        Self {
+            kernel_radius,
+            rest_density,
+            iterations: 3,
+            relaxation: 10.,
+            viscosity: 0.2,
+            surface_tension: 0.001,
+        }
+    }
+}
+
+impl Default for FluidParams {
+    fn default() -> Self {
+        Self::for_spacing(1., 2.)
+    }
+}
+
+
+fn poly6(r: f32, h: f32) -> f32 {
+    if r >= h {
+        return 0.;
+    }
+    4. / (PI * h.powi(8)) * (h * h - r * r).powi(3)
+}
+
+fn spiky_gradient(r: Vec2, h: f32) -> Vec2 {
+    let len = r.length();
+    if (len >= h) || (len == 0.) {
+        return vec2(0., 0.);
+    }
+    -30. / (PI * h.powi(5)) * (h - len) * (h - len) * r / len
+}
+
+
+impl Space {
+    pub fn add_fluid_particle(&mut self, position: Vec2, radius: f32) -> usize {
+        let handle = self.add_particle(position, radius);
+        self.kinds[handle] = ParticleKind::Fluid;
+        self.colors[handle] = Color::new(0.2, 0.5, 0.9, 1.0);
+        handle
+    }
+    pub fn set_fluid_params(&mut self, params: FluidParams) {
+        self.fluid_params = params;
+    }
+    pub fn get_kind(&self, handle: usize) -> ParticleKind {
+        self.kinds[handle]
+    }
+
+    // fluid neighbors of every fluid particle, from the cells within the kernel radius around it
+    pub(super) fn fluid_neighbors(&mut self, fluid: &[usize]) -> Vec<Vec<usize>> {
+        self.grid.update(&self.positions);
+        let h = self.fluid_params.kernel_radius;
+        let periods = self.periods();
+        fluid.iter().map(|&i| {
+            let pos = self.positions[i];
+            self.candidates(pos - Vec2::splat(h), pos + Vec2::splat(h)).into_iter()
+                .filter(|&j| (j != i) && (self.kinds[j] == ParticleKind::Fluid) && (minimum_image(self.positions[j] - pos, periods).length() < h))
+                .collect()
+        }).collect()
+    }
+
+    pub fn apply_fluid_density(&mut self) {
+        let fluid: Vec<usize> = (0..self.n_objects).filter(|&i| self.kinds[i] == ParticleKind::Fluid).collect();
+        if fluid.is_empty() {
+            return;
+        }
+        let neighbors = self.fluid_neighbors(&fluid);
+        let params = self.fluid_params;
+        let h = params.kernel_radius;
+        let periods = self.periods();
+        let w_corr = poly6(0.2 * h, h);
+        let mut lambdas = vec![0.; self.n_objects];
+
+        for _ in 0..params.iterations {
+            for (k, &i) in fluid.iter().enumerate() {
+                let mut density = self.masses[i] * poly6(0., h);
+                let mut grad_i = vec2(0., 0.);
+                let mut grad_sum = 0.;
+                for &j in neighbors[k].iter() {
+                    let r = minimum_image(self.positions[i] - self.positions[j], periods);
+                    density += self.masses[j] * poly6(r.length(), h);
+                    let grad_j = self.masses[j] / params.rest_density * spiky_gradient(r, h);
+                    grad_i += grad_j;
+                    grad_sum += grad_j.length_squared();
+                }
+                grad_sum += grad_i.length_squared();
+                // only resist compression, the surface tension term keeps the free surface together
+                let c = (density / params.rest_density - 1.).max(0.);
+                lambdas[i] = -c / (grad_sum + params.relaxation);
+            }
+            let deltas: Vec<Vec2> = fluid.iter().enumerate().map(|(k, &i)| {
+                let mut delta = vec2(0., 0.);
+                for &j in neighbors[k].iter() {
+                    let r = minimum_image(self.positions[i] - self.positions[j], periods);
+                    let s_corr = -params.surface_tension * (poly6(r.length(), h) / w_corr).powi(4);
+                    delta += (lambdas[i] + lambdas[j] + s_corr) * spiky_gradient(r, h);
+                }
+                delta / params.rest_density
+            }).collect();
+            for (&i, delta) in fluid.iter().zip(deltas) {
+                self.positions[i] += delta;
+            }
+        }
+    }
+    // xsph viscosity, blending each fluid particle's velocity towards its neighbors'
+    pub fn apply_fluid_viscosity(&mut self) {
+        let fluid: Vec<usize> = (0..self.n_objects).filter(|&i| self.kinds[i] == ParticleKind::Fluid).collect();
+        if fluid.is_empty() || (self.fluid_params.viscosity == 0.) {
+            return;
+        }
+        let neighbors = self.fluid_neighbors(&fluid);
+        let h = self.fluid_params.kernel_radius;
+        let periods = self.periods();
+        let velocities: Vec<Vec2> = fluid.iter().enumerate().map(|(k, &i)| {
+            let v = self.get_velocity(i);
+            let mut density = self.masses[i] * poly6(0., h);
+            let mut blend = vec2(0., 0.);
+            for &j in neighbors[k].iter() {
+                let w = self.masses[j] * poly6(minimum_image(self.positions[i] - self.positions[j], periods).length(), h);
+                density += w;
+                blend += (self.get_velocity(j) - v) * w;
+            }
+            v + self.fluid_params.viscosity * blend / density
+        }).collect();
+        for (&i, v) in fluid.iter().zip(velocities) {
+            self.set_velocity(i, v);
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use macroquad::prelude::*;
+use super::to_screen;
+
+
+pub trait ForceField {
+    fn get_acceleration(&self, position: Vec2) -> Vec2;
+    fn draw(&self);
+}
+
+
+// pulls towards `position`, or pushes away for a negative strength, fading out to zero at `radius`
+#[derive(Clone)]
+pub struct Attractor {
+    pub position: Vec2,
+    pub strength: f32,
+    pub radius: f32,
+    pub falloff: f32,
+}
+
+impl Attractor {
+    pub fn new(position: Vec2, strength: f32, radius: f32, falloff: f32) -> Box<Self> {
+        Box::new(
+            Self {
+                position, strength, radius, falloff
+            }
+        )
+    }
+}
+
+impl ForceField for Attractor {
+    fn get_acceleration(&self, position: Vec2) -> Vec2 {
+        let to_center = self.position - position;
+        let dist = to_center.length();
+        if (dist >= self.radius) || (dist == 0.) {
+            return vec2(0., 0.);
+        }
+        to_center / dist * self.strength * (1. - dist / self.radius).powf(self.falloff)
+    }
+    fn draw(&self) {
+        let center = to_screen(self.position);
+        let color = if self.strength > 0. { DARKBLUE } else { MAROON };
+        draw_circle_lines(center.x, center.y, to_screen(vec2(self.radius, 0.)).x - to_screen(vec2(0., 0.)).x, 2., color);
+    }
+}
+
+
+#[derive(Clone)]
+pub struct WindZone {
+    pub min: Vec2,
+    pub max: Vec2,
+    pub acceleration: Vec2,
+}
+
+impl WindZone {
+    pub fn new(min: Vec2, max: Vec2, acceleration: Vec2) -> Box<Self> {
+        Box::new(
+            Self {
+                min, max, acceleration
+            }
+        )
+    }
+}
+
+impl ForceField for WindZone {
+    fn get_acceleration(&self, position: Vec2) -> Vec2 {
+        if (position.x < self.min.x) || (position.y < self.min.y) || (position.x > self.max.x) || (position.y > self.max.y) {
+            return vec2(0., 0.);
+        }
+        self.acceleration
+    }
+    fn draw(&self) {
+        let min = to_screen(self.min);
+        let max = to_screen(self.max);
+        draw_rectangle_lines(min.x, min.y, max.x - min.x, max.y - min.y, 2., DARKGREEN);
+    }
+}
+
+
+// swirls counter-clockwise around `position` (clockwise for a negative strength), fading out to zero at `radius`
+#[derive(Clone)]
+pub struct Vortex {
+    pub position: Vec2,
+    pub strength: f32,
+    pub radius: f32,
+}
+
+impl Vortex {
+    pub fn new(position: Vec2, strength: f32, radius: f32) -> Box<Self> {
+        Box::new(
+            Self {
+                position, strength, radius
+            }
+        )
+    }
+}
+
+impl ForceField for Vortex {
+    fn get_acceleration(&self, position: Vec2) -> Vec2 {
+        let from_center = position - self.position;
+        let dist = from_center.length();
+        if (dist >= self.radius) || (dist == 0.) {
+            return vec2(0., 0.);
+        }
+        from_center.perp() / dist * self.strength * (1. - dist / self.radius)
+    }
+    fn draw(&self) {
+        let center = to_screen(self.position);
+        draw_circle_lines(center.x, center.y, to_screen(vec2(self.radius, 0.)).x - to_screen(vec2(0., 0.)).x, 2., DARKPURPLE);
+    }
+}
@@ -0,0 +1,143 @@
+use macroquad::prelude::*;
+use super::{Space, ParticleKind, ContactTarget};
+
+
+// coulomb friction and rolling resistance between solid particles, and between solid particles and constraints
+#[derive(Clone, Copy, Debug)]
+pub struct GranularParams {
+    pub friction: f32,
+    pub rolling_resistance: f32,
+    pub iterations: usize,
+}
+
+impl Default for GranularParams {
+    fn default() -> Self {
+        Self {
+            friction: 0.5,
+            rolling_resistance: 0.1,
+            iterations: 4,
+        }
+    }
+}
+
+
+impl Space {
+    pub fn set_granular(&mut self, granular: Option<GranularParams>) {
+        self.granular = granular;
+    }
+    pub fn get_angular_velocity(&self, handle: usize) -> f32 {
+        self.angular_velocities[handle]
+    }
+
+    // works on the contacts found by this substep's constraints and collisions, treating each disk as I = m r^2 / 2;
+    // particles with a material bring their own friction coefficient, even when granular friction is off
+    pub fn apply_friction(&mut self, sub_dt: f32) {
+        let params = match self.granular {
+            Some(params) => params,
+            None if self.has_materials() => GranularParams { friction: 0., rolling_resistance: 0., ..Default::default() },
+            None => return,
+        };
+        let mut impulses = vec![0.; self.substep_contacts.len()];
+        for iteration in 0..params.iterations {
+            for (k, accumulated) in impulses.iter_mut().enumerate() {
+                let (i, other, n, depth) = self.substep_contacts[k];
+                if self.kinds[i] != ParticleKind::Solid {
+                    continue;
+                }
+                let t = n.perp();
+                let w_i = 1. / self.masses[i];
+                let mut slip = self.substep_displacement(i, sub_dt).dot(t) / sub_dt - self.angular_velocities[i] * self.radii[i];
+                let friction_i = self.friction_of(i, params.friction);
+                let (j, w_j, friction) = match other {
+                    ContactTarget::Particle(j) => {
+                        if self.kinds[j] != ParticleKind::Solid {
+                            continue;
+                        }
+                        slip -= self.substep_displacement(j, sub_dt).dot(t) / sub_dt + self.angular_velocities[j] * self.radii[j];
+                        (Some(j), 1. / self.masses[j], (friction_i * self.friction_of(j, params.friction)).sqrt())
+                    },
+                    ContactTarget::Constraint(_) => (None, 0., friction_i),
+                };
+                if w_i + w_j == 0. {
+                    continue;
+                }
+
+                // the friction impulse accumulates over the iterations and is bounded by the normal impulse of the contact
+                let normal_impulse = depth / ((w_i + w_j) * sub_dt);
+                let max_friction = friction * normal_impulse;
+                let total = (*accumulated + slip / (3. * (w_i + w_j))).clamp(-max_friction, max_friction);
+                let impulse = total - *accumulated;
+                *accumulated = total;
+                self.positions[i] -= impulse * w_i * sub_dt * t;
+                self.angular_velocities[i] += 2. * impulse * w_i / self.radii[i];
+                if let Some(j) = j {
+                    self.positions[j] += impulse * w_j * sub_dt * t;
+                    self.angular_velocities[j] += 2. * impulse * w_j / self.radii[j];
+                }
+                if iteration + 1 == params.iterations {
+                    let resistance = 2. * params.rolling_resistance * normal_impulse;
+                    Self::roll_resistance(&mut self.angular_velocities[i], resistance * w_i / self.radii[i]);
+                    if let Some(j) = j {
+                        Self::roll_resistance(&mut self.angular_velocities[j], resistance * w_j / self.radii[j]);
+                    }
+                }
+            }
+        }
+    }
+    fn roll_resistance(angular_velocity: &mut f32, max_change: f32) {
+        *angular_velocity -= angular_velocity.clamp(-max_change, max_change);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::HalfSpace;
+    use std::ops::RangeInclusive;
+
+    const FLOOR: f32 = 99.;
+    const REPOSE_RANGE: RangeInclusive<f32> = 15.0..=40.0;
+
+    // pours uneven grains onto the floor one at a time and measures the slope of the pile's flanks in degrees
+    fn repose_angle(params: GranularParams) -> f32 {
+        let mut space = Space::new();
+        space.set_gravity(vec2(0., 30.));
+        space.set_substeps(8);
+        space.set_global_damping(0.05);
+        space.set_granular(Some(params));
+        space.add_constraint(HalfSpace::new(vec2(0., FLOOR), vec2(0., -1.)));
+        space.add_constraint(HalfSpace::new(vec2(20., 0.), vec2(1., 0.)));
+        space.add_constraint(HalfSpace::new(vec2(80., 0.), vec2(-1., 0.)));
+        for step in 0..2400 {
+            if (step % 15 == 0) && (step < 1800) {
+                let jitter = ((step * 7919) % 13) as f32 / 12.;
+                space.add_particle(vec2(49.5 + jitter, 90.), 0.4 + 0.1 * ((step * 104729) % 7) as f32 / 6.);
+            }
+            space.update(1. / 60.);
+        }
+
+        let mut heights = [0f32; 100];
+        for i in 0..space.n_particles() {
+            let pos = space.get_position(i);
+            let bin = pos.x.floor() as usize;
+            heights[bin] = heights[bin].max(FLOOR - pos.y);
+        }
+        // a triangular pile of height h and flank angle a covers h^2 / tan(a), stray grains barely add to it
+        let peak = heights.iter().cloned().fold(0., f32::max);
+        let area: f32 = heights.iter().sum();
+        (peak * peak / area).atan().to_degrees()
+    }
+
+    #[test]
+    fn grains_pile_up_at_repose_angle() {
+        let angle = repose_angle(GranularParams { friction: 0.8, rolling_resistance: 0.5, ..Default::default() });
+        assert!(REPOSE_RANGE.contains(&angle), "repose angle {angle} outside {REPOSE_RANGE:?}");
+    }
+
+    #[test]
+    fn frictionless_grains_spread_out() {
+        let angle = repose_angle(GranularParams { friction: 0., rolling_resistance: 0., ..Default::default() });
+        assert!(angle < *REPOSE_RANGE.start(), "frictionless pile still stands at {angle}");
+    }
+}
@@ -37,14 +37,19 @@ impl Grid {
             }
         }
         for (uid, pos) in positions.iter().enumerate() {
-            let i = (pos.x / self.cellsize) as usize;
-            let j = (pos.y / self.cellsize) as usize;
+            let (i, j) = self.cell_of(*pos);
             self.cells[i][j].push(uid);
         }
     }
+    // positions outside the grid are clamped into the border cells
+    pub fn cell_of(&self, pos: Vec2) -> (usize, usize) {
+        let i = ((pos.x / self.cellsize).max(0.) as usize).min(self.width);
+        let j = ((pos.y / self.cellsize).max(0.) as usize).min(self.height);
+        (i, j)
+    }
     pub fn update_obj(&mut self, uid: usize, pos: Vec2) {
-        let i = (pos.x / self.cellsize) as isize;
-        let j = (pos.y / self.cellsize) as isize;
+        let (i, j) = self.cell_of(pos);
+        let (i, j) = (i as isize, j as isize);
         for col in self.cells[((i - 1).max(0) as usize)..(i as usize + 1)].iter_mut() {
             for cell in col[((j - 1).max(0) as usize)..(j as usize + 1)].iter_mut() {
                 match cell.iter().position(|&id| id == uid) {
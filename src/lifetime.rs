@@ -0,0 +1,75 @@
+use macroquad::prelude::*;
+use super::{Space, ParticleRemoval, RemovalCause, sample_gradient};
+
+
+// attributes following a particle's age over its lifetime: `colors` replaces its colour and `radius_scales`
+// multiplies the radius it was created with, both as evenly spaced stops from birth to expiry
+#[derive(Clone, Debug, Default)]
+pub struct AgeCurve {
+    pub colors: Vec<Color>,
+    pub radius_scales: Vec<f32>,
+}
+
+
+fn sample_curve(values: &[f32], t: f32) -> f32 {
+    match values.len() {
+        0 => 1.,
+        1 => values[0],
+        n => {
+            let x = t.clamp(0., 1.) * (n - 1) as f32;
+            let k = (x.floor() as usize).min(n - 2);
+            values[k] + (values[k + 1] - values[k]) * (x - k as f32)
+        },
+    }
+}
+
+
+impl Space {
+    // `None` lets the particle live until something else removes it
+    pub fn set_lifetime(&mut self, handle: usize, lifetime: Option<f32>) {
+        self.lifetimes[handle] = lifetime.unwrap_or(f32::INFINITY);
+    }
+    pub fn get_lifetime(&self, handle: usize) -> Option<f32> {
+        Some(self.lifetimes[handle]).filter(|lifetime| lifetime.is_finite())
+    }
+    // seconds of simulation since the particle was added
+    pub fn get_age(&self, handle: usize) -> f32 {
+        self.ages[handle]
+    }
+    pub fn add_age_curve(&mut self, curve: AgeCurve) -> usize {
+        self.age_curves.push(curve);
+        self.age_curves.len() - 1
+    }
+    // the curve only has an effect on particles with a lifetime
+    pub fn set_age_curve(&mut self, handle: usize, curve: Option<usize>) {
+        self.particle_age_curves[handle] = curve;
+    }
+
+    // expired particles go through `remove_particles` like any other removal, so links and handles stay valid
+    pub(super) fn apply_lifetimes(&mut self, dt: f32) {
+        for age in self.ages.iter_mut() {
+            *age += dt;
+        }
+        let expired: Vec<usize> = (0..self.n_objects).filter(|&i| self.ages[i] >= self.lifetimes[i]).collect();
+        for &particle in expired.iter() {
+            self.events.removals.push(ParticleRemoval { particle, position: self.positions[particle], cause: RemovalCause::Expired });
+        }
+        if !expired.is_empty() {
+            self.remove_particles(&expired);
+        }
+
+        for i in 0..self.n_objects {
+            let Some(curve) = self.particle_age_curves[i].and_then(|curve| self.age_curves.get(curve)) else {
+                continue;
+            };
+            if !self.lifetimes[i].is_finite() {
+                continue;
+            }
+            let t = self.ages[i] / self.lifetimes[i];
+            if !curve.colors.is_empty() {
+                self.colors[i] = sample_gradient(&curve.colors, t);
+            }
+            self.radii[i] = self.base_radii[i] * sample_curve(&curve.radius_scales, t);
+        }
+    }
+}
@@ -5,20 +5,6 @@ use std::f32::consts::PI;
 use ::rand::{rngs::StdRng, Rng, SeedableRng, thread_rng};
 
 
-fn spray(step: f32, scene: &mut Space, rng: &mut StdRng, origin: Vec2) {
-    let theta = (step * 6.).sin() * PI / 2. + PI / 2.;
-    
-    let r = (step * 5.0).sin();
-    let g = (step * 5.0 + 0.33 * 2.0 * PI).sin();
-    let b = (step * 5.0 + 0.66 * 2.0 * PI).sin();
-
-    let handle = scene.add_particle(origin, rng.gen_range(0.3..0.7));
-    scene.set_velocity(handle, vec2(theta.cos() / screen_width() * 100., theta.sin() / screen_height() * 100.));
-    scene.set_color(handle, Color::new(r * r, g * g, b * b, 1.0));
-}
-
-
-
 #[macroquad::main("2D Particle Physics Simulation")]
 async fn main() {
     request_new_screen_size(640., 400.);
@@ -30,55 +16,183 @@ async fn main() {
     let mut scene = Space::new();
     scene.set_gravity(vec2(0., 30.));
     scene.set_substeps(8);
+    scene.set_fixed_timestep(1. / 60.);
+    scene.set_global_damping(0.05);
     // scene.add_constraint(CircleConstraint::new(vec2(50., 50.), 45.));
-    scene.add_constraint(HalfSpace::new(vec2(0., 99.), vec2(0., -1.)));
+    let floor = scene.add_constraint(HalfSpace::new(vec2(0., 99.), vec2(0., -1.)));
     scene.add_constraint(HalfSpace::new(vec2(0., 1.), vec2(0., 1.)));
     scene.add_constraint(HalfSpace::new(vec2(99., 0.), vec2(-1., 0.)));
     scene.add_constraint(HalfSpace::new(vec2(1., 0.), vec2(1., 0.)));
 
     let max_balls = 7200;
     let spray_origin = vec2(30., 40.);
+    let rainbow = vec![RED, YELLOW, GREEN, SKYBLUE, BLUE, MAGENTA, RED];
+    let mut sprays: Vec<usize> = Vec::new();
+    let block_materials = [
+        scene.add_material(ParticleMaterial::wood()),
+        scene.add_material(ParticleMaterial::stone()),
+        scene.add_material(ParticleMaterial::rubber()),
+    ];
+    let mut next_material = 0;
+    let spark_curve = scene.add_age_curve(AgeCurve {
+        colors: vec![WHITE, YELLOW, ORANGE, Color::new(0.8, 0.1, 0.1, 0.)],
+        radius_scales: vec![1., 0.8, 0.3],
+    });
     let mut rng = StdRng::seed_from_u64(15485748);
     let mut n_balls = 0;
     let mut paused = false;
     let mut dragging = false;
     let mut current_block = Vec::new();
     let particle_radius = 0.5;
+    let mut grabbed: Option<usize> = None;
+    let mut grab_target = vec2(0., 0.);
+    let mut grab_target_velocity = vec2(0., 0.);
+    let mut fan: Option<usize> = None;
+    let mut drain: Option<usize> = None;
+    let mut sticky = false;
+    let mut granular = false;
+    let mut heating = false;
 
     loop {
         iteration += 1;
         clear_background(BLACK);
         dt = get_frame_time();
 
-        // if n_balls < max_balls {
-        //     n_balls += 1;
-        //     spray(iteration as f32 / 800., &mut scene, &mut rng, spray_origin);
-        //     spray(iteration as f32 / 800., &mut scene, &mut rng, spray_origin + vec2(40., 0.));
-        // }
+        if is_key_pressed(KeyCode::E) {
+            if sprays.is_empty() {
+                for (k, origin) in [spray_origin, spray_origin + vec2(40., 0.)].into_iter().enumerate() {
+                    sprays.push(scene.add_emitter(EmitterParams {
+                        position: origin,
+                        spread: PI / 2.,
+                        speed: 75.0..75.0,
+                        radius: 0.3..0.7,
+                        gradient: rainbow.clone(),
+                        gradient_period: 4.,
+                        max_count: Some(max_balls / 2),
+                        seed: k as u64,
+                        ..Default::default()
+                    }));
+                }
+            } else {
+                for handle in sprays.drain(..) {
+                    scene.remove_emitter(handle);
+                }
+            }
+        }
         if is_key_pressed(KeyCode::R) {
             scene.clear();
             n_balls = 0;
+            grabbed = None;
+        }
+        if is_key_pressed(KeyCode::X) {
+            if let Some(pos) = scene.localize(vec2(mouse_position().0, mouse_position().1)) {
+                scene.explode(pos, 15., 80.);
+            }
+        }
+        if is_key_pressed(KeyCode::F) {
+            match fan {
+                Some(handle) => {
+                    scene.remove_force_field(handle);
+                    fan = None;
+                },
+                None => {
+                    if let Some(pos) = scene.localize(vec2(mouse_position().0, mouse_position().1)) {
+                        fan = Some(scene.add_force_field(WindZone::new(pos - vec2(5., 30.), pos + vec2(5., 0.), vec2(0., -90.))));
+                    }
+                },
+            }
+        }
+        if is_key_pressed(KeyCode::V) {
+            match drain {
+                Some(handle) => {
+                    scene.remove_region(handle);
+                    drain = None;
+                },
+                None => {
+                    if let Some(pos) = scene.localize(vec2(mouse_position().0, mouse_position().1)) {
+                        drain = Some(scene.add_kill_zone(CircleRegion::new(pos, 4.)));
+                    }
+                },
+            }
+        }
+        if is_key_pressed(KeyCode::T) {
+            if let Some(pos) = scene.localize(vec2(mouse_position().0, mouse_position().1)) {
+                scene.add_emitter(EmitterParams {
+                    position: pos,
+                    direction: vec2(0., -1.),
+                    spread: PI,
+                    rate: 0.,
+                    burst: 60,
+                    speed: 20.0..60.0,
+                    radius: 0.2..0.4,
+                    lifetime: Some(0.1),
+                    particle_lifetime: Some(0.5..1.5),
+                    age_curve: Some(spark_curve),
+                    seed: iteration as u64,
+                    ..Default::default()
+                });
+            }
+        }
+        if is_key_pressed(KeyCode::C) {
+            sticky = !sticky;
+            scene.set_cohesion(if sticky { Some(Cohesion { strength: 40., radius: 0.3 }) } else { None });
+        }
+        if is_key_pressed(KeyCode::G) {
+            granular = !granular;
+            scene.set_granular(if granular { Some(GranularParams::default()) } else { None });
+        }
+        if is_key_pressed(KeyCode::H) {
+            heating = !heating;
+            scene.set_thermal(if heating { Some(ThermalParams::default()) } else { None });
+            scene.set_constraint_temperature(floor, if heating { Some(1500.) } else { None });
         }
         if is_key_pressed(KeyCode::B) {
             match scene.localize(vec2(mouse_position().0, mouse_position().1)) {
                 Some(pos) => {
+                    // wood, stone and rubber in turn
+                    let material = block_materials[next_material];
+                    next_material = (next_material + 1) % block_materials.len();
                     let mut particles = Vec::new();
-                    let col = Color::new(rng.gen_range(0.2..0.9), rng.gen_range(0.2..0.9), rng.gen_range(0.2..0.9), 1.0);
                     let rad = if is_key_down(KeyCode::Z) { 10 } else { 2 };
                     for i in -rad..=rad {
                         for j in -rad..=rad {
                             let particle_pos = pos + vec2(i as f32 * (particle_radius * 2.), j as f32 * (particle_radius * 2.));
-                            let handle = scene.add_particle(particle_pos, particle_radius);
-                            scene.set_color(handle, col);
-                            particles.push(handle);
+                            particles.push(scene.add_particle(particle_pos, particle_radius));
                             n_balls += 1;
                         }
                     }
-                    scene.add_block(particles, 0.04);
+                    scene.add_block_with_material(particles, material);
                 },
                 None => ()
             }
         }
+        if is_key_pressed(KeyCode::L) {
+            if let Some(pos) = scene.localize(vec2(mouse_position().0, mouse_position().1)) {
+                let rope = scene.add_rope(pos, pos + vec2(20., 0.), 24, 0.4);
+                scene.disable_adjacent_collisions(&rope.particles);
+                scene.pin(rope.particles[0]);
+                n_balls += rope.particles.len();
+            }
+        }
+        if is_key_pressed(KeyCode::K) {
+            if let Some(pos) = scene.localize(vec2(mouse_position().0, mouse_position().1)) {
+                let cloth = scene.add_cloth(pos, pos + vec2(16., 12.), 17, 13, 0.4);
+                scene.pin(cloth.particle(0, 0));
+                scene.pin(cloth.particle(cloth.columns - 1, 0));
+                for &handle in cloth.particles.iter() {
+                    scene.set_color(handle, Color::new(0.8, 0.3, 0.3, 1.0));
+                }
+                n_balls += cloth.particles.len();
+            }
+        }
+        if is_key_down(KeyCode::W) {
+            if let Some(pos) = scene.localize(vec2(mouse_position().0, mouse_position().1)) {
+                if !scene.is_colliding(pos, particle_radius) {
+                    scene.add_fluid_particle(pos, particle_radius);
+                    n_balls += 1;
+                }
+            }
+        }
         if is_key_down(KeyCode::Space) {
             dragging = true;
             paused = true;
@@ -99,14 +213,54 @@ async fn main() {
                 scene.set_color(*uid, col);
             }
             scene.add_block(current_block.clone(), 0.04);
+            // hand placed particles overlap, keep them from pushing against their own links
+            scene.add_collision_group(&current_block);
             current_block.clear();
             dragging = false;
             paused = false;
         }
 
-        if !paused { scene.update(dt) };
+        let mouse = scene.localize(vec2(mouse_position().0, mouse_position().1));
+        if is_mouse_button_pressed(MouseButton::Left) {
+            if let Some(pos) = mouse {
+                grabbed = scene.query_point(pos).or_else(|| scene.nearest(pos, 2.))
+                    .map(|particle| scene.add_attachment(particle, pos, 2000., 3000.));
+                if let Some(handle) = grabbed {
+                    scene.set_attachment_damping(handle, 40.);
+                }
+                grab_target = pos;
+                grab_target_velocity = vec2(0., 0.);
+            }
+        }
+        if let Some(handle) = grabbed {
+            if let Some(pos) = mouse {
+                if dt > 0. {
+                    grab_target_velocity = grab_target_velocity.lerp((pos - grab_target) / dt, 0.5);
+                }
+                grab_target = pos;
+            }
+            if is_mouse_button_down(MouseButton::Left) {
+                scene.set_attachment_target(handle, grab_target);
+            } else {
+                let particle = scene.attachment(handle).unwrap().particle;
+                scene.set_velocity(particle, grab_target_velocity);
+                scene.remove_attachment(handle);
+                grabbed = None;
+            }
+        }
+
+        if !paused { scene.step(dt); }
+        // the attachment disappears with its particle; forget it right away, before a new pin can take its slot
+        if grabbed.is_some_and(|handle| scene.attachment(handle).is_none()) {
+            grabbed = None;
+        }
         scene.draw_debug();
         scene.draw();
+        if let Some(attachment) = grabbed.and_then(|handle| scene.attachment(handle)) {
+            let from = scene.project(scene.get_position(attachment.particle));
+            let to = scene.project(grab_target);
+            draw_line(from.x, from.y, to.x, to.y, 2., YELLOW);
+        }
 
         draw_text_ex(
             &format!("FPS: {}", get_fps()),
@@ -118,6 +272,11 @@ async fn main() {
             10.0, 60.0, 
             TextParams {font: font, font_size: 24u16, color: GRAY, ..Default::default()}
         );
+        draw_text_ex(
+            &format!("Broken links: {}", scene.total_broken_links()),
+            10.0, 90.0,
+            TextParams {font, font_size: 24u16, color: GRAY, ..Default::default()}
+        );
         next_frame().await
     }
 }
@@ -0,0 +1,182 @@
+use macroquad::prelude::*;
+use std::f32::consts::PI;
+use super::{Space, ContactTarget, Integrator, ParticleKind};
+
+
+// approaching slower than this (in units/s) counts as resting contact and does not bounce
+const RESTITUTION_THRESHOLD: f32 = 1.;
+// reach of the cohesion between materials when no global cohesion sets one
+const MATERIAL_COHESION_RADIUS: f32 = 0.3;
+
+
+// mass comes from density times the disk area; friction and restitution are combined per contact, cohesion
+// is the strength of the attraction between surfaces, and links of blocks made of the material get its
+// stiffness (infinite for rigid links) and strength. `kind` is the phase particles of the material are in,
+// so converting a particle between a fluid and a solid material melts or freezes it
+#[derive(Clone, Debug)]
+pub struct ParticleMaterial {
+    pub name: String,
+    pub density: f32,
+    pub friction: f32,
+    pub restitution: f32,
+    pub cohesion: f32,
+    pub link_stiffness: f32,
+    pub link_strength: f32,
+    pub color: Color,
+    pub kind: ParticleKind,
+}
+
+impl ParticleMaterial {
+    pub fn wood() -> Self {
+        Self {
+            name: "wood".to_string(),
+            density: 0.6,
+            friction: 0.6,
+            restitution: 0.2,
+            cohesion: 0.,
+            link_stiffness: 20000.,
+            link_strength: 0.1,
+            color: Color::new(0.6, 0.4, 0.2, 1.0),
+            kind: ParticleKind::Solid,
+        }
+    }
+    pub fn stone() -> Self {
+        Self {
+            name: "stone".to_string(),
+            density: 2.5,
+            friction: 0.8,
+            restitution: 0.05,
+            cohesion: 0.,
+            link_stiffness: f32::INFINITY,
+            link_strength: 0.06,
+            color: Color::new(0.5, 0.5, 0.55, 1.0),
+            kind: ParticleKind::Solid,
+        }
+    }
+    pub fn rubber() -> Self {
+        Self {
+            name: "rubber".to_string(),
+            density: 1.1,
+            friction: 0.9,
+            restitution: 0.8,
+            cohesion: 0.,
+            link_stiffness: 200.,
+            link_strength: 0.5,
+            color: Color::new(0.9, 0.3, 0.5, 1.0),
+            kind: ParticleKind::Solid,
+        }
+    }
+}
+
+
+impl Space {
+    pub fn add_material(&mut self, material: ParticleMaterial) -> usize {
+        self.materials.push(material);
+        self.materials.len() - 1
+    }
+    pub fn material(&self, material: usize) -> Option<&ParticleMaterial> {
+        self.materials.get(material)
+    }
+    pub fn find_material(&self, name: &str) -> Option<usize> {
+        self.materials.iter().position(|material| material.name == name)
+    }
+    pub fn add_particle_with_material(&mut self, position: Vec2, radius: f32, material: usize) -> usize {
+        let handle = self.add_particle(position, radius);
+        self.set_material(handle, Some(material));
+        handle
+    }
+    // sets the mass, colour and kind from the material; `None` only forgets it, leaving them as they are
+    pub fn set_material(&mut self, handle: usize, material: Option<usize>) {
+        self.particle_materials[handle] = material;
+        if let Some(material) = material.map(|material| &self.materials[material]) {
+            self.masses[handle] = material.density * PI * self.radii[handle] * self.radii[handle];
+            self.colors[handle] = material.color;
+            self.kinds[handle] = material.kind;
+        }
+    }
+    pub fn get_material(&self, handle: usize) -> Option<usize> {
+        self.particle_materials[handle]
+    }
+    // like `add_soft_block`, with the links and the particles taking after the material, whose link stiffness
+    // must be positive
+    pub fn add_block_with_material(&mut self, particles: Vec<usize>, material: usize) {
+        let ParticleMaterial { link_stiffness, link_strength, .. } = self.materials[material];
+        if link_stiffness.is_nan() || (link_stiffness <= 0.) {
+            panic!("Link stiffness of {} must be positive, got {link_stiffness}", self.materials[material].name);
+        }
+        for &handle in particles.iter() {
+            self.set_material(handle, Some(material));
+        }
+        self.add_soft_block(particles, 1. / link_stiffness, 0., link_strength);
+    }
+
+    // per-particle values, falling back to the global setting for particles without a material
+    pub(super) fn friction_of(&self, handle: usize, fallback: f32) -> f32 {
+        self.particle_materials[handle].map_or(fallback, |material| self.materials[material].friction)
+    }
+    pub(super) fn cohesion_of(&self, handle: usize, fallback: f32) -> f32 {
+        self.particle_materials[handle].map_or(fallback, |material| self.materials[material].cohesion)
+    }
+    fn restitution_of(&self, handle: usize) -> f32 {
+        self.particle_materials[handle].map_or(0., |material| self.materials[material].restitution)
+    }
+    pub(super) fn has_materials(&self) -> bool {
+        self.particle_materials.iter().any(|material| material.is_some())
+    }
+    pub(super) fn has_material_cohesion(&self) -> bool {
+        self.particle_materials.iter().flatten().any(|&material| self.materials[material].cohesion > 0.)
+    }
+    pub(super) fn material_cohesion_radius(&self) -> f32 {
+        self.cohesion.map_or(MATERIAL_COHESION_RADIUS, |cohesion| cohesion.radius)
+    }
+
+    // velocity including the corrections made so far in this substep, whatever the integrator
+    fn solved_velocity(&self, handle: usize, sub_dt: f32) -> Vec2 {
+        let correction = (self.positions[handle] - self.positions_old[handle]) / sub_dt;
+        match self.integrator {
+            Integrator::SemiImplicitEuler | Integrator::VelocityVerlet => self.velocities[handle] + correction,
+            Integrator::Verlet | Integrator::Xpbd => correction,
+        }
+    }
+    // velocities before the substep's corrections, empty when nothing can bounce
+    pub(super) fn restitution_velocities(&self, sub_dt: f32) -> Vec<Vec2> {
+        if !self.particle_materials.iter().flatten().any(|&material| self.materials[material].restitution > 0.) {
+            return Vec::new();
+        }
+        (0..self.n_objects).map(|i| self.solved_velocity(i, sub_dt)).collect()
+    }
+    // the position based contacts stop the approach entirely, give back the bouncing part of it
+    pub(super) fn apply_restitution(&mut self, before: &[Vec2], sub_dt: f32) {
+        if before.is_empty() {
+            return;
+        }
+        for k in 0..self.substep_contacts.len() {
+            let (i, other, n, _) = self.substep_contacts[k];
+            let (j, restitution) = match other {
+                ContactTarget::Particle(j) => (Some(j), self.restitution_of(i).max(self.restitution_of(j))),
+                ContactTarget::Constraint(_) => (None, self.restitution_of(i)),
+            };
+            if restitution == 0. {
+                continue;
+            }
+            let w_i = 1. / self.masses[i];
+            let w_j = j.map_or(0., |j| 1. / self.masses[j]);
+            if w_i + w_j == 0. {
+                continue;
+            }
+            let approach = (before[i] - j.map_or(vec2(0., 0.), |j| before[j])).dot(n);
+            if approach > -RESTITUTION_THRESHOLD {
+                continue;
+            }
+            let current = (self.solved_velocity(i, sub_dt) - j.map_or(vec2(0., 0.), |j| self.solved_velocity(j, sub_dt))).dot(n);
+            let dv = -restitution * approach - current;
+            if dv <= 0. {
+                continue;
+            }
+            self.positions[i] += dv * sub_dt * w_i / (w_i + w_j) * n;
+            if let Some(j) = j {
+                self.positions[j] -= dv * sub_dt * w_j / (w_i + w_j) * n;
+            }
+        }
+    }
+}
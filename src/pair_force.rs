@@ -0,0 +1,82 @@
+use macroquad::prelude::*;
+use rayon::prelude::*;
+#[path = "quadtree.rs"] mod quadtree;
+pub use quadtree::*;
+use super::{Space, minimum_image};
+
+
+// short-range attraction between particle surfaces closer than `radius`, strongest when touching
+#[derive(Clone, Copy, Debug)]
+pub struct Cohesion {
+    pub strength: f32,
+    pub radius: f32,
+}
+
+// long-range n-body attraction, approximated with a barnes-hut tree
+#[derive(Clone, Copy, Debug)]
+pub struct Gravitation {
+    pub constant: f32,
+    pub theta: f32,
+    pub softening: f32,
+}
+
+
+impl Space {
+    pub fn set_cohesion(&mut self, cohesion: Option<Cohesion>) {
+        self.cohesion = cohesion;
+    }
+    pub fn set_gravitation(&mut self, gravitation: Option<Gravitation>) {
+        self.gravitation = gravitation;
+    }
+    pub fn set_mass(&mut self, handle: usize, mass: f32) {
+        self.masses[handle] = mass;
+    }
+    pub fn get_mass(&self, handle: usize) -> f32 {
+        self.masses[handle]
+    }
+
+    pub fn apply_pair_forces(&mut self) {
+        if let Some(cohesion) = self.cohesion {
+            self.apply_cohesion(cohesion);
+        } else if self.has_material_cohesion() {
+            self.apply_cohesion(Cohesion { strength: 0., radius: self.material_cohesion_radius() });
+        }
+        if let Some(gravitation) = self.gravitation {
+            self.apply_gravitation(gravitation);
+        }
+    }
+    // a pair sticks as weakly as its weaker member; particles without a material use the global strength
+    fn apply_cohesion(&mut self, cohesion: Cohesion) {
+        self.grid.update(&self.positions);
+        self.grid_dirty = false;
+        let reach = Vec2::splat(cohesion.radius + 2. * self.max_radius());
+        let periods = self.periods();
+        for i in 0..self.n_objects {
+            let pos = self.positions[i];
+            for j in self.candidates(pos - reach, pos + reach) {
+                if j <= i {
+                    continue;
+                }
+                let axis = minimum_image(self.positions[j] - pos, periods);
+                let dist = axis.length();
+                let gap = (dist - self.radii[i] - self.radii[j]).max(0.);
+                if (gap >= cohesion.radius) || (dist == 0.) {
+                    continue;
+                }
+                let strength = self.cohesion_of(i, cohesion.strength).min(self.cohesion_of(j, cohesion.strength));
+                let force = axis / dist * strength * (1. - gap / cohesion.radius);
+                self.accelerations[i] += force / self.masses[i];
+                self.accelerations[j] -= force / self.masses[j];
+            }
+        }
+    }
+    fn apply_gravitation(&mut self, gravitation: Gravitation) {
+        let tree = QuadTree::new(&self.positions, &self.masses);
+        let accelerations: Vec<Vec2> = self.positions.par_iter().enumerate()
+            .map(|(i, pos)| tree.get_acceleration(i, *pos, gravitation.constant, gravitation.theta, gravitation.softening))
+            .collect();
+        for (accel, pull) in self.accelerations.iter_mut().zip(accelerations) {
+            *accel += pull;
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use macroquad::prelude::*;
+
+
+const LEAF_CAPACITY: usize = 4;
+const MAX_DEPTH: usize = 16;
+
+
+#[derive(Clone)]
+struct Node {
+    size: f32,
+    mass: f32,
+    center_of_mass: Vec2,
+    children: Vec<usize>,
+    particles: Vec<usize>,
+}
+
+
+// barnes-hut tree over point masses, rebuilt from scratch whenever the positions change
+#[derive(Clone)]
+pub struct QuadTree {
+    nodes: Vec<Node>,
+    positions: Vec<Vec2>,
+    masses: Vec<f32>,
+}
+
+impl QuadTree {
+    pub fn new(positions: &[Vec2], masses: &[f32]) -> Self {
+        let mut tree = Self { nodes: Vec::new(), positions: positions.to_vec(), masses: masses.to_vec() };
+        if positions.is_empty() {
+            return tree;
+        }
+        let mut min = positions[0];
+        let mut max = positions[0];
+        for pos in positions.iter() {
+            min = min.min(*pos);
+            max = max.max(*pos);
+        }
+        let size = (max - min).max_element().max(f32::EPSILON);
+        tree.build((0..positions.len()).collect(), min, size, 0);
+        tree
+    }
+    fn build(&mut self, particles: Vec<usize>, min: Vec2, size: f32, depth: usize) -> usize {
+        let mut mass = 0.;
+        let mut weighted = vec2(0., 0.);
+        for &i in particles.iter() {
+            mass += self.masses[i];
+            weighted += self.positions[i] * self.masses[i];
+        }
+        let center_of_mass = if mass > 0. { weighted / mass } else { min + Vec2::splat(size / 2.) };
+        let handle = self.nodes.len();
+        self.nodes.push(Node { size, mass, center_of_mass, children: Vec::new(), particles: Vec::new() });
+
+        if (particles.len() <= LEAF_CAPACITY) || (depth >= MAX_DEPTH) {
+            self.nodes[handle].particles = particles;
+            return handle;
+        }
+        let half = size / 2.;
+        let mid = min + Vec2::splat(half);
+        let mut quadrants = [Vec::new(), Vec::new(), Vec::new(), Vec::new()];
+        for i in particles {
+            let q = (self.positions[i].x >= mid.x) as usize + 2 * (self.positions[i].y >= mid.y) as usize;
+            quadrants[q].push(i);
+        }
+        for (q, quadrant) in quadrants.into_iter().enumerate() {
+            if quadrant.is_empty() {
+                continue;
+            }
+            let child_min = min + vec2((q % 2) as f32 * half, (q / 2) as f32 * half);
+            let child = self.build(quadrant, child_min, half, depth + 1);
+            self.nodes[handle].children.push(child);
+        }
+        handle
+    }
+
+    // nodes that look smaller than `theta` from `position` are treated as a single mass
+    pub fn get_acceleration(&self, handle: usize, position: Vec2, constant: f32, theta: f32, softening: f32) -> Vec2 {
+        let mut accel = vec2(0., 0.);
+        if self.nodes.is_empty() {
+            return accel;
+        }
+        let pull = |to: Vec2, mass: f32| {
+            let d = to - position;
+            let dist2 = d.length_squared() + softening * softening;
+            constant * mass * d / (dist2 * dist2.sqrt())
+        };
+        let mut stack = vec![0];
+        while let Some(n) = stack.pop() {
+            let node = &self.nodes[n];
+            if node.children.is_empty() {
+                for &j in node.particles.iter() {
+                    if j != handle {
+                        accel += pull(self.positions[j], self.masses[j]);
+                    }
+                }
+                continue;
+            }
+            let dist = (node.center_of_mass - position).length();
+            if node.size < theta * dist {
+                accel += pull(node.center_of_mass, node.mass);
+            } else {
+                stack.extend_from_slice(&node.children);
+            }
+        }
+        accel
+    }
+}
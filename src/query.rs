@@ -0,0 +1,172 @@
+use macroquad::prelude::*;
+use super::Space;
+
+
+#[derive(Clone, Copy, Debug)]
+pub struct RayHit {
+    pub particle: usize,
+    pub distance: f32,
+    pub point: Vec2,
+    pub normal: Vec2,
+}
+
+
+impl Space {
+    // the grid goes stale whenever particles are added, moved or removed outside of `update`
+    fn refresh_grid(&mut self) {
+        if self.grid_dirty {
+            self.grid.update(&self.positions);
+            self.grid_dirty = false;
+        }
+    }
+    // handles of the particles whose center lies in the cells overlapping [min, max]; callers pad the box by
+    // `max_radius` to find every particle reaching into it. periodic axes continue on the other side of the grid
+    pub(super) fn candidates(&self, min: Vec2, max: Vec2) -> Vec<usize> {
+        let periods = self.periods();
+        let xs = self.cell_span(min.x, max.x, self.grid.width, periods.x > 0.);
+        let ys = self.cell_span(min.y, max.y, self.grid.height, periods.y > 0.);
+        let mut found = Vec::new();
+        for &x in xs.iter() {
+            for &y in ys.iter() {
+                found.extend_from_slice(self.grid.get(x, y));
+            }
+        }
+        found
+    }
+    // grid columns or rows covering [min, max] along one axis, each at most once
+    fn cell_span(&self, min: f32, max: f32, cells: usize, periodic: bool) -> Vec<usize> {
+        let (first, last) = ((min / self.grid.cellsize).floor() as isize, (max / self.grid.cellsize).floor() as isize);
+        if !periodic {
+            return (first.clamp(0, cells as isize) as usize..=last.clamp(0, cells as isize) as usize).collect();
+        }
+        if last - first + 1 >= cells as isize {
+            return (0..cells).collect();
+        }
+        (first..=last).map(|cell| cell.rem_euclid(cells as isize) as usize).collect()
+    }
+    pub(super) fn max_radius(&self) -> f32 {
+        self.radii.iter().cloned().fold(0., f32::max)
+    }
+
+    pub fn query_radius(&mut self, center: Vec2, radius: f32) -> Vec<usize> {
+        self.refresh_grid();
+        let reach = Vec2::splat(radius + self.max_radius());
+        self.candidates(center - reach, center + reach).into_iter()
+            .filter(|&i| (self.positions[i] - center).length() < (radius + self.radii[i]))
+            .collect()
+    }
+    pub fn query_aabb(&mut self, min: Vec2, max: Vec2) -> Vec<usize> {
+        self.refresh_grid();
+        let pad = Vec2::splat(self.max_radius());
+        self.candidates(min - pad, max + pad).into_iter()
+            .filter(|&i| (self.positions[i].clamp(min, max) - self.positions[i]).length() < self.radii[i])
+            .collect()
+    }
+    pub fn query_point(&mut self, point: Vec2) -> Option<usize> {
+        self.query_radius(point, 0.).into_iter()
+            .min_by(|&a, &b| (self.positions[a] - point).length().total_cmp(&(self.positions[b] - point).length()))
+    }
+    pub fn nearest(&mut self, point: Vec2, max_dist: f32) -> Option<usize> {
+        self.refresh_grid();
+        self.candidates(point - Vec2::splat(max_dist), point + Vec2::splat(max_dist)).into_iter()
+            .map(|i| (i, (self.positions[i] - point).length()))
+            .filter(|(_, dist)| *dist <= max_dist)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(i, _)| i)
+    }
+
+    pub fn raycast(&mut self, origin: Vec2, dir: Vec2, max_dist: f32) -> Option<RayHit> {
+        self.shape_cast(origin, dir, 0., max_dist)
+    }
+    // sweeps a circle of `radius` along the ray, walking the grid cells it crosses in order
+    pub fn shape_cast(&mut self, origin: Vec2, dir: Vec2, radius: f32, max_dist: f32) -> Option<RayHit> {
+        self.refresh_grid();
+        let dir = dir.normalize_or_zero();
+        if dir == Vec2::ZERO {
+            return None;
+        }
+        let cellsize = self.grid.cellsize;
+        // a particle reaches into the swept circle from up to its own radius away
+        let reach = 1 + ((radius + self.max_radius()) / cellsize).ceil() as isize;
+
+        let mut cell = ((origin.x / cellsize).floor() as isize, (origin.y / cellsize).floor() as isize);
+        let step = (if dir.x > 0. { 1 } else { -1 }, if dir.y > 0. { 1 } else { -1 });
+        let t_delta = vec2(cellsize / dir.x.abs(), cellsize / dir.y.abs());
+        let first_crossing = |c: isize, s: isize, o: f32, d: f32| {
+            if d == 0. {
+                return f32::INFINITY;
+            }
+            ((c + if s > 0 { 1 } else { 0 }) as f32 * cellsize - o) / d
+        };
+        let mut t_max = vec2(first_crossing(cell.0, step.0, origin.x, dir.x), first_crossing(cell.1, step.1, origin.y, dir.y));
+        let mut t_entry = 0.;
+        let mut best: Option<RayHit> = None;
+
+        let (width, height) = (self.grid.width as isize, self.grid.height as isize);
+        while t_entry <= max_dist {
+            // past the grid by more than the reach and heading further away, no cell left can hold a hit
+            let away_x = ((cell.0 < -reach) && (dir.x <= 0.)) || ((cell.0 > width + reach) && (dir.x >= 0.));
+            let away_y = ((cell.1 < -reach) && (dir.y <= 0.)) || ((cell.1 > height + reach) && (dir.y >= 0.));
+            if away_x || away_y {
+                break;
+            }
+            for dx in -reach..=reach {
+                for dy in -reach..=reach {
+                    let (x, y) = (cell.0 + dx, cell.1 + dy);
+                    if (x < 0) || (y < 0) || (x > width) || (y > height) {
+                        continue;
+                    }
+                    for &i in self.grid.get(x as usize, y as usize).iter() {
+                        let hit = match ray_circle(origin, dir, self.positions[i], self.radii[i] + radius) {
+                            Some(t) if t <= max_dist => t,
+                            _ => continue,
+                        };
+                        if best.is_none_or(|b| hit < b.distance) {
+                            let point = origin + dir * hit;
+                            let normal = (point - self.positions[i]).normalize_or_zero();
+                            best = Some(RayHit {
+                                particle: i,
+                                distance: hit,
+                                point,
+                                normal: if normal == Vec2::ZERO { -dir } else { normal },
+                            });
+                        }
+                    }
+                }
+            }
+            // any later cell is entered after the best hit, so nothing closer can follow
+            if best.is_some_and(|b| b.distance <= t_entry) {
+                break;
+            }
+            if t_max.x < t_max.y {
+                t_entry = t_max.x;
+                t_max.x += t_delta.x;
+                cell.0 += step.0;
+            } else {
+                t_entry = t_max.y;
+                t_max.y += t_delta.y;
+                cell.1 += step.1;
+            }
+        }
+        best
+    }
+}
+
+
+// distance along a normalized ray to the first intersection with a circle, 0 if the origin is inside it
+fn ray_circle(origin: Vec2, dir: Vec2, center: Vec2, radius: f32) -> Option<f32> {
+    let m = origin - center;
+    let b = m.dot(dir);
+    let c = m.dot(m) - radius * radius;
+    if c <= 0. {
+        return Some(0.);
+    }
+    if b > 0. {
+        return None;
+    }
+    let disc = b * b - c;
+    if disc < 0. {
+        return None;
+    }
+    Some(-b - disc.sqrt())
+}
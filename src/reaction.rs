@@ -0,0 +1,134 @@
+use macroquad::prelude::*;
+use std::collections::BTreeSet;
+use super::{Space, ContactTarget, ParticleKind, ParticleRemoval, RemovalCause, insert_slot, minimum_image};
+
+
+// what happens to the two particles of a reaction, `first` being the one made of the reaction's first material.
+// a spawned particle appears between the two when both are removed, otherwise beside them, clear of the survivors
+#[derive(Clone, Copy, Debug)]
+pub enum ReactionEffect {
+    Convert { first: Option<usize>, second: Option<usize> },
+    Spawn { material: usize, radius: f32 },
+    Remove { first: bool, second: bool },
+    Link { strength: f32 },
+    Unlink,
+}
+
+// fires when particles of the two materials touch, in either order
+#[derive(Clone, Debug)]
+pub struct Reaction {
+    pub first: usize,
+    pub second: usize,
+    pub effects: Vec<ReactionEffect>,
+}
+
+
+impl Space {
+    pub fn add_reaction(&mut self, reaction: Reaction) -> usize {
+        insert_slot(&mut self.reactions, reaction)
+    }
+    pub fn remove_reaction(&mut self, handle: usize) {
+        if let Some(slot) = self.reactions.get_mut(handle) {
+            *slot = None;
+        }
+    }
+
+    // the first reaction matching the pair's materials, with the pair reordered to match it
+    fn find_reaction(&self, p1: usize, p2: usize) -> Option<(usize, usize, usize)> {
+        let (m1, m2) = (self.particle_materials[p1]?, self.particle_materials[p2]?);
+        self.reactions.iter().enumerate().find_map(|(handle, slot)| match slot {
+            Some(reaction) if (reaction.first, reaction.second) == (m1, m2) => Some((handle, p1, p2)),
+            Some(reaction) if (reaction.first, reaction.second) == (m2, m1) => Some((handle, p2, p1)),
+            _ => None,
+        })
+    }
+
+    // runs after every substep on its particle contacts and on fluid particles within the kernel radius of each
+    // other, visited in handle order with reactions in registration order; a particle takes part in at most one
+    // reaction per substep. removals happen together at the end, followed by the spawned particles, so the
+    // handles used along the way stay valid
+    pub(super) fn apply_reactions(&mut self) {
+        if self.reactions.iter().all(|slot| slot.is_none()) {
+            return;
+        }
+        let mut pairs: BTreeSet<(usize, usize)> = self.substep_contacts.iter()
+            .filter_map(|(i, other, _, _)| match other {
+                ContactTarget::Particle(j) => Some(((*i).min(*j), (*i).max(*j))),
+                ContactTarget::Constraint(_) => None,
+            })
+            .collect();
+        // fluid particles keep their distance through the density solve instead of colliding with each other
+        let fluid: Vec<usize> = (0..self.n_objects).filter(|&i| self.kinds[i] == ParticleKind::Fluid).collect();
+        if fluid.len() > 1 {
+            for (&i, neighbors) in fluid.iter().zip(self.fluid_neighbors(&fluid)) {
+                pairs.extend(neighbors.into_iter().map(|j| (i.min(j), i.max(j))));
+            }
+        }
+        let mut reacted = BTreeSet::new();
+        let mut removed = BTreeSet::new();
+        let mut spawns = Vec::new();
+        for (p1, p2) in pairs {
+            if reacted.contains(&p1) || reacted.contains(&p2) {
+                continue;
+            }
+            let Some((handle, first, second)) = self.find_reaction(p1, p2) else {
+                continue;
+            };
+            reacted.insert(p1);
+            reacted.insert(p2);
+            let effects = self.reactions[handle].as_ref().unwrap().effects.clone();
+            for effect in effects {
+                match effect {
+                    ReactionEffect::Convert { first: into_first, second: into_second } => {
+                        if into_first.is_some() {
+                            self.set_material(first, into_first);
+                        }
+                        if into_second.is_some() {
+                            self.set_material(second, into_second);
+                        }
+                    },
+                    ReactionEffect::Spawn { material, radius } => {
+                        spawns.push((first, second, radius, material));
+                    },
+                    ReactionEffect::Remove { first: remove_first, second: remove_second } => {
+                        for (particle, remove) in [(first, remove_first), (second, remove_second)] {
+                            if remove && removed.insert(particle) {
+                                self.events.removals.push(ParticleRemoval { particle, position: self.positions[particle], cause: RemovalCause::Reaction(handle) });
+                            }
+                        }
+                    },
+                    ReactionEffect::Link { strength } => {
+                        if !self.link_exists(first, second) {
+                            self.add_link(first, second, strength);
+                        }
+                    },
+                    ReactionEffect::Unlink => {
+                        while let Some(link) = self.links.iter().position(|&(a, b)| (a.min(b), a.max(b)) == (p1, p2)) {
+                            self.remove_link(link);
+                        }
+                    },
+                }
+            }
+        }
+        let spawns: Vec<(Vec2, f32, usize)> = spawns.into_iter()
+            .map(|(first, second, radius, material)| (self.spawn_position(first, second, radius, &removed), radius, material))
+            .collect();
+        if !removed.is_empty() {
+            self.remove_particles(&removed.into_iter().collect::<Vec<usize>>());
+        }
+        for (position, radius, material) in spawns {
+            self.add_particle_with_material(position, radius, material);
+        }
+    }
+    // the midpoint of the pair, pushed sideways until a particle of `radius` there overlaps neither survivor
+    fn spawn_position(&self, first: usize, second: usize, radius: f32, removed: &BTreeSet<usize>) -> Vec2 {
+        let axis = minimum_image(self.positions[second] - self.positions[first], self.periods());
+        let midpoint = self.positions[first] + 0.5 * axis;
+        let half = 0.5 * axis.length();
+        let offset = [first, second].into_iter()
+            .filter(|particle| !removed.contains(particle))
+            .map(|particle| ((self.radii[particle] + radius).powi(2) - half * half).max(0.).sqrt())
+            .fold(0., f32::max);
+        midpoint + offset * axis.try_normalize().unwrap_or(vec2(1., 0.)).perp()
+    }
+}
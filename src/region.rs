@@ -0,0 +1,209 @@
+use macroquad::prelude::*;
+use std::collections::BTreeSet;
+use super::{Space, ParticleRemoval, RemovalCause, insert_slot, to_screen};
+
+
+pub trait Region {
+    fn contains(&self, position: Vec2) -> bool;
+    fn draw(&self);
+}
+
+
+#[derive(Clone)]
+pub struct CircleRegion {
+    pub center: Vec2,
+    pub radius: f32,
+}
+
+impl CircleRegion {
+    pub fn new(center: Vec2, radius: f32) -> Box<Self> {
+        Box::new(
+            Self {
+                center, radius
+            }
+        )
+    }
+}
+
+impl Region for CircleRegion {
+    fn contains(&self, position: Vec2) -> bool {
+        (position - self.center).length() < self.radius
+    }
+    fn draw(&self) {
+        let center = to_screen(self.center);
+        draw_circle_lines(center.x, center.y, to_screen(vec2(self.radius, 0.)).x - to_screen(vec2(0., 0.)).x, 2., GOLD);
+    }
+}
+
+
+#[derive(Clone)]
+pub struct BoxRegion {
+    pub min: Vec2,
+    pub max: Vec2,
+}
+
+impl BoxRegion {
+    pub fn new(min: Vec2, max: Vec2) -> Box<Self> {
+        Box::new(
+            Self {
+                min, max
+            }
+        )
+    }
+}
+
+impl Region for BoxRegion {
+    fn contains(&self, position: Vec2) -> bool {
+        (position.x >= self.min.x) && (position.y >= self.min.y) && (position.x < self.max.x) && (position.y < self.max.y)
+    }
+    fn draw(&self) {
+        let min = to_screen(self.min);
+        let max = to_screen(self.max);
+        draw_rectangle_lines(min.x, min.y, max.x - min.x, max.y - min.y, 2., GOLD);
+    }
+}
+
+
+// simple polygon, convex or not, given by its corners in order
+#[derive(Clone)]
+pub struct PolygonRegion {
+    pub points: Vec<Vec2>,
+}
+
+impl PolygonRegion {
+    pub fn new(points: Vec<Vec2>) -> Box<Self> {
+        Box::new(
+            Self {
+                points
+            }
+        )
+    }
+}
+
+impl Region for PolygonRegion {
+    // even-odd rule: count the edges crossed by a ray going right from `position`
+    fn contains(&self, position: Vec2) -> bool {
+        let mut inside = false;
+        for (i, a) in self.points.iter().enumerate() {
+            let b = self.points[(i + 1) % self.points.len()];
+            if (a.y > position.y) != (b.y > position.y) {
+                let x = a.x + (position.y - a.y) / (b.y - a.y) * (b.x - a.x);
+                if position.x < x {
+                    inside = !inside;
+                }
+            }
+        }
+        inside
+    }
+    fn draw(&self) {
+        for (i, a) in self.points.iter().enumerate() {
+            let p1 = to_screen(*a);
+            let p2 = to_screen(self.points[(i + 1) % self.points.len()]);
+            draw_line(p1.x, p1.y, p2.x, p2.y, 2., GOLD);
+        }
+    }
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RegionPhase {
+    Enter,
+    Stay,
+    Leave,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct RegionEvent {
+    pub region: usize,
+    pub particle: usize,
+    pub phase: RegionPhase,
+}
+
+
+pub(super) struct RegionSlot {
+    shape: Box<dyn Region>,
+    kill: bool,
+    inside: BTreeSet<usize>,
+}
+
+
+impl Space {
+    // reports the particles entering, staying in and leaving the region after every update, without touching them
+    pub fn add_region(&mut self, shape: Box<dyn Region>) -> usize {
+        insert_slot(&mut self.regions, RegionSlot { shape, kill: false, inside: BTreeSet::new() })
+    }
+    // removes every particle whose center ends an update inside the region
+    pub fn add_kill_zone(&mut self, shape: Box<dyn Region>) -> usize {
+        insert_slot(&mut self.regions, RegionSlot { shape, kill: true, inside: BTreeSet::new() })
+    }
+    pub fn remove_region(&mut self, handle: usize) {
+        if let Some(slot) = self.regions.get_mut(handle) {
+            *slot = None;
+        }
+    }
+    pub fn particles_in_region(&self, handle: usize) -> Vec<usize> {
+        self.regions.get(handle).and_then(|slot| slot.as_ref())
+            .map_or(Vec::new(), |region| region.inside.iter().copied().collect())
+    }
+    pub fn region_count(&self, handle: usize) -> usize {
+        self.regions.get(handle).and_then(|slot| slot.as_ref()).map_or(0, |region| region.inside.len())
+    }
+    pub fn region_events(&self) -> &[RegionEvent] {
+        &self.events.region_events
+    }
+    pub fn drain_region_events(&mut self) -> Vec<RegionEvent> {
+        std::mem::take(&mut self.events.region_events)
+    }
+
+    // kill zones go first so the events of the other regions only mention particles that still exist
+    pub(super) fn apply_regions(&mut self) {
+        let mut killed = BTreeSet::new();
+        for (handle, slot) in self.regions.iter().enumerate() {
+            let Some(region) = slot.as_ref().filter(|region| region.kill) else {
+                continue;
+            };
+            for particle in 0..self.n_objects {
+                if region.shape.contains(self.positions[particle]) && killed.insert(particle) {
+                    self.events.removals.push(ParticleRemoval { particle, position: self.positions[particle], cause: RemovalCause::KillZone(handle) });
+                }
+            }
+        }
+        if !killed.is_empty() {
+            self.remove_particles(&killed.into_iter().collect::<Vec<usize>>());
+        }
+
+        for (handle, slot) in self.regions.iter_mut().enumerate() {
+            let Some(region) = slot else {
+                continue;
+            };
+            if region.kill {
+                continue;
+            }
+            let inside: BTreeSet<usize> = (0..self.n_objects).filter(|&i| region.shape.contains(self.positions[i])).collect();
+            for &particle in region.inside.difference(&inside) {
+                self.events.region_events.push(RegionEvent { region: handle, particle, phase: RegionPhase::Leave });
+            }
+            for &particle in inside.iter() {
+                let phase = if region.inside.contains(&particle) { RegionPhase::Stay } else { RegionPhase::Enter };
+                self.events.region_events.push(RegionEvent { region: handle, particle, phase });
+            }
+            region.inside = inside;
+        }
+    }
+    // removed particles leave their regions silently and the other handles are renumbered
+    pub(super) fn remap_particle_regions(&mut self, remap: &[Option<usize>]) {
+        for region in self.regions.iter_mut().flatten() {
+            region.inside = region.inside.iter().filter_map(|&i| remap[i]).collect();
+        }
+    }
+    pub(super) fn empty_regions(&mut self) {
+        for region in self.regions.iter_mut().flatten() {
+            region.inside.clear();
+        }
+    }
+    pub(super) fn draw_regions(&self) {
+        for region in self.regions.iter().flatten() {
+            region.shape.draw();
+        }
+    }
+}
@@ -1,30 +1,145 @@
 #[path = "grid.rs"] mod grid;
 pub use grid::*;
+#[path = "integrator.rs"] mod integrator;
+pub use integrator::*;
+#[path = "events.rs"] mod events;
+pub use events::*;
+#[path = "query.rs"] mod query;
+pub use query::*;
+#[path = "attachment.rs"] mod attachment;
+pub use attachment::*;
+#[path = "force_field.rs"] mod force_field;
+pub use force_field::*;
+#[path = "pair_force.rs"] mod pair_force;
+pub use pair_force::*;
+#[path = "fluid.rs"] mod fluid;
+pub use fluid::*;
+#[path = "granular.rs"] mod granular;
+pub use granular::*;
+#[path = "cloth.rs"] mod cloth;
+pub use cloth::*;
+#[path = "filter.rs"] mod filter;
+pub use filter::*;
+#[path = "region.rs"] mod region;
+pub use region::*;
+#[path = "bounds.rs"] mod bounds;
+pub use bounds::*;
+#[path = "emitter.rs"] mod emitter;
+pub use emitter::*;
+#[path = "lifetime.rs"] mod lifetime;
+pub use lifetime::*;
+#[path = "user_data.rs"] mod user_data;
+pub use user_data::*;
+#[path = "material.rs"] mod material;
+pub use material::*;
+#[path = "thermal.rs"] mod thermal;
+pub use thermal::*;
+#[path = "reaction.rs"] mod reaction;
+pub use reaction::*;
 
-use itertools::{izip, iproduct, Itertools};
+use itertools::{iproduct, Itertools};
 use macroquad::prelude::*;
 use rayon::prelude::*;
-use std::{sync::{Arc, Mutex, RwLock}, thread::current};
+use std::{collections::HashSet, sync::{Arc, Mutex, RwLock}, thread::current};
 use num_cpus;
 
 
 
+// drops the entries of a column whose flag is false, keeping the order of the others
+fn retain_kept<T>(column: &mut Vec<T>, keep: &[bool]) {
+    let mut i = 0;
+    column.retain(|_| {
+        i += 1;
+        keep[i - 1]
+    });
+}
+// fills the first free slot of a handle-indexed collection, or a new one at the end, and returns its handle
+pub(super) fn insert_slot<T>(slots: &mut Vec<Option<T>>, value: T) -> usize {
+    match slots.iter().position(|slot| slot.is_none()) {
+        Some(handle) => {
+            slots[handle] = Some(value);
+            handle
+        },
+        None => {
+            slots.push(Some(value));
+            slots.len() - 1
+        },
+    }
+}
+// world coordinates span 100 units along the smaller side of the window, centered along the larger one
+pub(super) fn to_screen(pos: Vec2) -> Vec2 {
+    let smaller_dim = screen_height().min(screen_width());
+    let x_shift = screen_width() - smaller_dim;
+    let y_shift = screen_height() - smaller_dim;
+    pos / 100. * smaller_dim + vec2(x_shift / 2., y_shift / 2.)
+}
+
+
 pub struct Space {
     positions: Vec<Vec2>,
     positions_old: Vec<Vec2>,
+    positions_prev: Vec<Vec2>,
+    velocities: Vec<Vec2>,
     accelerations: Vec<Vec2>,
+    accelerations_old: Vec<Vec2>,
+    masses: Vec<f32>,
+    gravity_scales: Vec<f32>,
+    dampings: Vec<f32>,
     radii: Vec<f32>,
     colors: Vec<Color>,
+    kinds: Vec<ParticleKind>,
+    angular_velocities: Vec<f32>,
+    filters: Vec<CollisionFilter>,
+    ages: Vec<f32>,
+    lifetimes: Vec<f32>,
+    base_radii: Vec<f32>,
+    particle_age_curves: Vec<Option<usize>>,
+    user_data: Vec<Option<UserData>>,
+    particle_materials: Vec<Option<usize>>,
+    temperatures: Vec<f32>,
 
     links: Vec<(usize, usize)>,
     link_dists: Vec<f32>,
     link_strengths: Vec<f32>,
+    link_compliances: Vec<f32>,
+    link_dampings: Vec<f32>,
+    link_lambdas: Vec<f32>,
+    link_iterations: usize,
+    attachments: Vec<Option<Attachment>>,
+    ignored_pairs: HashSet<(usize, usize)>,
+    n_collision_groups: usize,
     grid: Grid,
+    grid_dirty: bool,
     constraints: Vec<Box<dyn Constraint>>,
+    constraint_masks: Vec<u32>,
+    constraint_temperatures: Vec<Option<f32>>,
+    force_fields: Vec<Option<Box<dyn ForceField>>>,
+    regions: Vec<Option<RegionSlot>>,
+    emitters: Vec<Option<EmitterSlot>>,
+    age_curves: Vec<AgeCurve>,
+    materials: Vec<ParticleMaterial>,
+    reactions: Vec<Option<Reaction>>,
+    events: Events,
+    substep_contacts: Vec<(usize, ContactTarget, Vec2, f32)>,
 
     n_objects: usize,
     dt_substeps: usize,
+    dt_prev: f32,
     gravity: Vec2,
+    damping: f32,
+    cohesion: Option<Cohesion>,
+    gravitation: Option<Gravitation>,
+    fluid_params: FluidParams,
+    granular: Option<GranularParams>,
+    thermal: Option<ThermalParams>,
+    integrator: Integrator,
+    out_of_bounds: OutOfBounds,
+    periodic: BVec2,
+
+    fixed_dt: f32,
+    max_steps: usize,
+    accumulator: f32,
+    alpha: f32,
 }
 
 impl Space {
@@ -33,27 +148,91 @@ impl Space {
         Self {
             positions: Vec::new(),
             positions_old: Vec::new(),
+            positions_prev: Vec::new(),
+            velocities: Vec::new(),
             accelerations: Vec::new(),
+            accelerations_old: Vec::new(),
+            masses: Vec::new(),
+            gravity_scales: Vec::new(),
+            dampings: Vec::new(),
             radii: Vec::new(),
             colors: Vec::new(),
+            kinds: Vec::new(),
+            angular_velocities: Vec::new(),
+            filters: Vec::new(),
+            ages: Vec::new(),
+            lifetimes: Vec::new(),
+            base_radii: Vec::new(),
+            particle_age_curves: Vec::new(),
+            user_data: Vec::new(),
+            particle_materials: Vec::new(),
+            temperatures: Vec::new(),
 
             links: Vec::new(),
             link_dists: Vec::new(),
             link_strengths: Vec::new(),
+            link_compliances: Vec::new(),
+            link_dampings: Vec::new(),
+            link_lambdas: Vec::new(),
+            link_iterations: 3,
+            attachments: Vec::new(),
+            ignored_pairs: HashSet::new(),
+            n_collision_groups: 0,
             grid: Grid::new((100. / cellsize) as usize, (100. / cellsize) as usize, cellsize),
+            grid_dirty: false,
             constraints: Vec::new(),
+            constraint_masks: Vec::new(),
+            constraint_temperatures: Vec::new(),
+            force_fields: Vec::new(),
+            regions: Vec::new(),
+            emitters: Vec::new(),
+            age_curves: Vec::new(),
+            materials: Vec::new(),
+            reactions: Vec::new(),
+            events: Events::default(),
+            substep_contacts: Vec::new(),
 
             n_objects: 0,
             dt_substeps: 1,
+            dt_prev: 0.,
             gravity: vec2(0., 0.),
+            damping: 0.,
+            cohesion: None,
+            gravitation: None,
+            fluid_params: FluidParams::default(),
+            granular: None,
+            thermal: None,
+            integrator: Integrator::Verlet,
+            out_of_bounds: OutOfBounds::default(),
+            periodic: BVec2::new(false, false),
+
+            fixed_dt: 1. / 60.,
+            max_steps: 5,
+            accumulator: 0.,
+            alpha: 1.,
         }
     }
     pub fn set_gravity(&mut self, gravity: Vec2) {
         self.gravity = gravity;
     }
+    // linear drag in 1/s applied to every particle on top of its own damping
+    pub fn set_global_damping(&mut self, damping: f32) {
+        self.damping = damping;
+    }
+    // more substeps stiffen links and contacts, but see `Integrator` for the precision limit of verlet and xpbd
     pub fn set_substeps(&mut self, substeps: usize) {
         self.dt_substeps = substeps;
     }
+    // a zero or negative step would never drain the accumulator
+    pub fn set_fixed_timestep(&mut self, dt: f32) {
+        self.fixed_dt = dt.max(f32::EPSILON);
+    }
+    pub fn set_max_steps(&mut self, max_steps: usize) {
+        self.max_steps = max_steps.max(1);
+    }
+    pub fn set_link_iterations(&mut self, iterations: usize) {
+        self.link_iterations = iterations;
+    }
     pub fn localize(&mut self, pos: Vec2) -> Option<Vec2> {
         let smaller_dim = screen_height().min(screen_width());
         let x_shift = screen_width() - smaller_dim;
@@ -67,26 +246,88 @@ impl Space {
         }
         return Some(normalized);
     }
+    pub fn project(&self, pos: Vec2) -> Vec2 {
+        to_screen(pos)
+    }
+    pub fn n_particles(&self) -> usize {
+        self.n_objects
+    }
     
     pub fn add_particle(&mut self, position: Vec2, radius: f32) -> usize {
         self.positions.push(position);
         self.positions_old.push(position);
+        self.positions_prev.push(position);
         self.radii.push(radius);
         self.colors.push(WHITE);
+        self.kinds.push(ParticleKind::Solid);
+        self.angular_velocities.push(0.);
+        self.filters.push(CollisionFilter::default());
+        self.ages.push(0.);
+        self.lifetimes.push(f32::INFINITY);
+        self.base_radii.push(radius);
+        self.particle_age_curves.push(None);
+        self.user_data.push(None);
+        self.particle_materials.push(None);
+        self.temperatures.push(self.ambient_temperature());
+        self.velocities.push(vec2(0., 0.));
         self.accelerations.push(vec2(0., 0.));
+        self.accelerations_old.push(vec2(0., 0.));
+        self.masses.push(1.);
+        self.gravity_scales.push(1.);
+        self.dampings.push(0.);
         self.n_objects += 1;
+        self.grid_dirty = true;
         self.n_objects - 1
     }
-    pub fn add_constraint(&mut self, constraint: Box<dyn Constraint>) {
+    pub fn add_constraint(&mut self, constraint: Box<dyn Constraint>) -> usize {
         self.constraints.push(constraint);
+        self.constraint_masks.push(u32::MAX);
+        self.constraint_temperatures.push(None);
+        self.constraints.len() - 1
+    }
+    pub fn add_force_field(&mut self, field: Box<dyn ForceField>) -> usize {
+        insert_slot(&mut self.force_fields, field)
     }
+    pub fn remove_force_field(&mut self, handle: usize) {
+        if let Some(slot) = self.force_fields.get_mut(handle) {
+            *slot = None;
+        }
+    }
+    // one-shot radial impulse, strongest at the center and fading out to zero at `radius`
+    pub fn explode(&mut self, center: Vec2, radius: f32, strength: f32) {
+        for i in self.query_radius(center, radius) {
+            let from_center = self.positions[i] - center;
+            let dist = from_center.length();
+            if (dist == 0.) || (dist >= radius) {
+                continue;
+            }
+            let velocity = self.get_velocity(i) + from_center / dist * strength * (1. - dist / radius);
+            self.set_velocity(i, velocity);
+        }
+    }
+    // rigid rod that breaks once it is stretched or compressed by more than `strength` (as a strain)
     pub fn add_link(&mut self, p1: usize, p2: usize, strength: f32) {
+        self.add_spring(p1, p2, 0., 0., strength);
+    }
+    // xpbd spring: compliance is the inverse stiffness and damping a damping coefficient, both independent of the substep count
+    pub fn add_spring(&mut self, p1: usize, p2: usize, compliance: f32, damping: f32, strength: f32) {
         if (p1 >= self.n_objects) || (p2 >= self.n_objects) {
             panic!("Point out of range");
         }
         self.links.push((p1, p2));
-        self.link_dists.push((self.positions[p2] - self.positions[p1]).length());
+        self.link_dists.push(minimum_image(self.positions[p2] - self.positions[p1], self.periods()).length());
         self.link_strengths.push(strength);
+        self.link_compliances.push(compliance);
+        self.link_dampings.push(damping);
+        self.link_lambdas.push(0.);
+    }
+    fn remove_link(&mut self, i: usize) {
+        self.links.remove(i);
+        self.link_dists.remove(i);
+        self.link_strengths.remove(i);
+        self.link_compliances.remove(i);
+        self.link_dampings.remove(i);
+        self.link_lambdas.remove(i);
     }
     pub fn link_exists(&self, p1: usize, p2: usize) -> bool {
         if self.links.contains(&(p1, p2)) || self.links.contains(&(p2, p1)) {
@@ -95,6 +336,9 @@ impl Space {
         return false;
     }
     pub fn add_block(&mut self, particles: Vec<usize>, link_strength: f32) {
+        self.add_soft_block(particles, 0., 0., link_strength);
+    }
+    pub fn add_soft_block(&mut self, particles: Vec<usize>, compliance: f32, damping: f32, link_strength: f32) {
         for i in 0..particles.len() {
             let mut nearest = [self.n_objects; 8];
             let uid = particles[i];
@@ -112,35 +356,104 @@ impl Space {
             }
             for near_id in nearest {
                 if !self.link_exists(uid, near_id) && (near_id != self.n_objects) {
-                    self.add_link(uid, near_id, link_strength);
+                    self.add_spring(uid, near_id, compliance, damping, link_strength);
                 }
             }
         }
     }
     pub fn remove_particle(&mut self, handle: usize) {
-        self.positions.remove(handle);
-        self.positions_old.remove(handle);
-        self.accelerations.remove(handle);
-        self.radii.remove(handle);
-        self.colors.remove(handle);
-        self.n_objects -= 1;
-        for i in (0..self.links.len()).rev() {
-            if (self.links[i].0 == handle) || (self.links[i].1 == handle) {
-                self.links.remove(i);
-                self.link_dists.remove(i);
-                self.link_strengths.remove(i);
+        self.remove_particles(&[handle]);
+    }
+    // removes all the given particles in one pass; the handles of the remaining ones shift down to stay contiguous
+    pub fn remove_particles(&mut self, handles: &[usize]) {
+        let mut keep = vec![true; self.n_objects];
+        for &handle in handles {
+            keep[handle] = false;
+        }
+        for &handle in handles {
+            if let Some(data) = self.user_data[handle].take() {
+                self.events.removed_user_data.push((handle, data));
             }
         }
+        let mut remap = Vec::with_capacity(self.n_objects);
+        let mut n_kept = 0;
+        for &kept in keep.iter() {
+            remap.push(if kept { Some(n_kept) } else { None });
+            n_kept += kept as usize;
+        }
+        retain_kept(&mut self.positions, &keep);
+        retain_kept(&mut self.positions_old, &keep);
+        retain_kept(&mut self.positions_prev, &keep);
+        retain_kept(&mut self.velocities, &keep);
+        retain_kept(&mut self.accelerations, &keep);
+        retain_kept(&mut self.accelerations_old, &keep);
+        retain_kept(&mut self.masses, &keep);
+        retain_kept(&mut self.gravity_scales, &keep);
+        retain_kept(&mut self.dampings, &keep);
+        retain_kept(&mut self.radii, &keep);
+        retain_kept(&mut self.colors, &keep);
+        retain_kept(&mut self.kinds, &keep);
+        retain_kept(&mut self.angular_velocities, &keep);
+        retain_kept(&mut self.filters, &keep);
+        retain_kept(&mut self.ages, &keep);
+        retain_kept(&mut self.lifetimes, &keep);
+        retain_kept(&mut self.base_radii, &keep);
+        retain_kept(&mut self.particle_age_curves, &keep);
+        retain_kept(&mut self.user_data, &keep);
+        retain_kept(&mut self.particle_materials, &keep);
+        retain_kept(&mut self.temperatures, &keep);
+        self.n_objects = n_kept;
+        self.grid_dirty = true;
+        self.events.remap_particles(&remap);
+        self.remap_particle_attachments(&remap);
+        self.remap_particle_ignored_pairs(&remap);
+        self.remap_particle_regions(&remap);
+
+        let keep_links: Vec<bool> = self.links.iter().map(|(p1, p2)| keep[*p1] && keep[*p2]).collect();
+        retain_kept(&mut self.links, &keep_links);
+        retain_kept(&mut self.link_dists, &keep_links);
+        retain_kept(&mut self.link_strengths, &keep_links);
+        retain_kept(&mut self.link_compliances, &keep_links);
+        retain_kept(&mut self.link_dampings, &keep_links);
+        retain_kept(&mut self.link_lambdas, &keep_links);
+        for (p1, p2) in self.links.iter_mut() {
+            *p1 = remap[*p1].unwrap();
+            *p2 = remap[*p2].unwrap();
+        }
     }
     pub fn clear(&mut self) {
         self.positions.clear();
         self.positions_old.clear();
+        self.positions_prev.clear();
+        self.velocities.clear();
         self.accelerations.clear();
+        self.accelerations_old.clear();
+        self.masses.clear();
+        self.gravity_scales.clear();
+        self.dampings.clear();
         self.radii.clear();
         self.colors.clear();
+        self.kinds.clear();
+        self.angular_velocities.clear();
+        self.filters.clear();
+        self.ages.clear();
+        self.lifetimes.clear();
+        self.base_radii.clear();
+        self.particle_age_curves.clear();
+        self.user_data.clear();
+        self.particle_materials.clear();
+        self.temperatures.clear();
         self.links.clear();
         self.link_dists.clear();
         self.link_strengths.clear();
+        self.link_compliances.clear();
+        self.link_dampings.clear();
+        self.link_lambdas.clear();
+        self.attachments.clear();
+        self.ignored_pairs.clear();
+        self.empty_regions();
+        self.events.clear_contacts();
+        self.grid_dirty = true;
         self.n_objects = 0;
     }
 
@@ -148,27 +461,44 @@ impl Space {
         (self.positions[p2] - self.positions[p1]).length() < (self.radii[p1] + self.radii[p2])
     }
     pub fn is_colliding(&mut self, pos: Vec2, radius: f32) -> bool {
-        for i in 0..self.n_objects {
-            if (self.positions[i] - pos).length() < (radius + self.radii[i]) {
-                return true;
-            }
-        }
-        return false;
+        !self.query_radius(pos, radius).is_empty()
     }
 
     pub fn set_position(&mut self, handle: usize, position: Vec2) {
         let delta = self.positions[handle] - position;
         self.positions[handle] = position;
         self.positions_old[handle] += delta;
+        self.positions_prev[handle] = position;
+        self.grid_dirty = true;
     }
-    pub fn get_position(&mut self, handle: usize) -> Vec2 {
+    pub fn get_position(&self, handle: usize) -> Vec2 {
         self.positions[handle]
     }
     pub fn set_color(&mut self, handle: usize, color: Color) {
         self.colors[handle] = color;
     }
+    // duration of the last substep, which the implicit verlet velocity is measured over
+    fn velocity_dt(&self) -> f32 {
+        if self.dt_prev > 0. {
+            return self.dt_prev;
+        }
+        self.fixed_dt / self.dt_substeps as f32
+    }
     pub fn set_velocity(&mut self, handle: usize, velocity: Vec2) {
-        self.positions_old[handle] = self.positions[handle] - velocity;
+        self.velocities[handle] = velocity;
+        self.positions_old[handle] = self.positions[handle] - velocity * self.velocity_dt();
+    }
+    pub fn get_velocity(&self, handle: usize) -> Vec2 {
+        if self.integrator.has_velocities() {
+            return self.velocities[handle];
+        }
+        (self.positions[handle] - self.positions_old[handle]) / self.velocity_dt()
+    }
+    pub fn set_gravity_scale(&mut self, handle: usize, scale: f32) {
+        self.gravity_scales[handle] = scale;
+    }
+    pub fn set_damping(&mut self, handle: usize, damping: f32) {
+        self.dampings[handle] = damping;
     }
     pub fn set_acceleration(&mut self, handle: usize, acceleration: Vec2) {
         self.accelerations[handle] = acceleration;
@@ -177,71 +507,152 @@ impl Space {
         self.accelerations[handle] += force;
     }
 
+    pub fn step(&mut self, frame_dt: f32) -> usize {
+        self.events.clear();
+        self.accumulator += frame_dt;
+        let mut steps = 0;
+        while (self.accumulator >= self.fixed_dt) && (steps < self.max_steps) {
+            self.advance(self.fixed_dt);
+            self.accumulator -= self.fixed_dt;
+            steps += 1;
+        }
+        // drop the backlog instead of trying to catch up after a hitch
+        if self.accumulator >= self.fixed_dt {
+            self.accumulator %= self.fixed_dt;
+        }
+        self.alpha = self.accumulator / self.fixed_dt;
+        steps
+    }
     pub fn update(&mut self, dt: f32) {
+        self.events.clear();
+        self.advance(dt);
+    }
+    fn advance(&mut self, dt: f32) {
+        self.apply_emitters(dt);
+        self.positions_prev.clone_from(&self.positions);
         let sub_dt = dt / self.dt_substeps as f32;
         for _ in 0..self.dt_substeps {
             self.apply_gravity();
-            self.apply_constraints();
-            self.apply_links();
-            self.remove_outside();
-            self.grid.update(&self.positions);
-            self.apply_collisions();
-
-            for (i, (pos, pos_old, accel)) in izip!(self.positions.iter_mut(), self.positions_old.iter_mut(), self.accelerations.iter_mut()).enumerate() {
-                let v = *pos - *pos_old;
-                *pos_old = *pos;
-                *pos = *pos + v + *accel * sub_dt * sub_dt;
-                *accel = vec2(0., 0.);
-            }
+            self.apply_force_fields();
+            self.apply_pair_forces();
+            self.substep(sub_dt);
+            self.apply_reactions();
         }
+        self.apply_lifetimes(dt);
+        self.apply_regions();
+        self.events.finish_contacts();
+        self.grid_dirty = true;
+        self.alpha = 1.;
     }
-    pub fn remove_outside(&mut self) {
-        for i in (0..self.n_objects).rev() {
-            if (self.positions[i].x < 0.0) || (self.positions[i].x >= 100.0) || (self.positions[i].y < 0.0) || (self.positions[i].y >= 100.0) {
-                self.remove_particle(i);
-            }
-        }
+    fn solve_positions(&mut self, sub_dt: f32) {
+        // removals shift handles, so they happen before this substep's contacts are collected
+        self.apply_bounds();
+        self.substep_contacts.clear();
+        let velocities = self.restitution_velocities(sub_dt);
+        self.apply_constraints(sub_dt);
+        self.apply_links(sub_dt);
+        self.grid.update(&self.positions);
+        let outside = self.outside_flags();
+        self.apply_collisions(&outside, sub_dt);
+        self.apply_unbounded_collisions(&outside, sub_dt);
+        self.apply_friction(sub_dt);
+        self.apply_restitution(&velocities, sub_dt);
+        self.apply_heat_transfer(sub_dt);
+        self.apply_fluid_density();
     }
     pub fn apply_gravity(&mut self) {
         for i in 0..self.n_objects {
-            self.accelerate(i, self.gravity);
+            self.accelerate(i, self.gravity * self.gravity_scales[i]);
         }
     }
-    pub fn apply_constraints(&mut self) {
-        for constraint in self.constraints.iter() {
-            for (pos, radius) in self.positions.iter_mut().zip(self.radii.iter()) {
-                match constraint.get_new_pos(*pos, *radius) {
-                    Some(new_pos) => {
-                        *pos = new_pos;
-                    },
-                    None => (),
+    pub fn apply_force_fields(&mut self) {
+        for field in self.force_fields.iter().flatten() {
+            for (pos, accel) in self.positions.iter().zip(self.accelerations.iter_mut()) {
+                *accel += field.get_acceleration(*pos);
+            }
+        }
+    }
+    pub fn apply_constraints(&mut self, sub_dt: f32) {
+        for (ci, constraint) in self.constraints.iter().enumerate() {
+            for (i, (pos, radius)) in self.positions.iter_mut().zip(self.radii.iter()).enumerate() {
+                if self.filters[i].layer & self.constraint_masks[ci] == 0 {
+                    continue;
+                }
+                if let Some(new_pos) = constraint.get_new_pos(*pos, *radius) {
+                    let correction = new_pos - *pos;
+                    let depth = correction.length();
+                    if depth > 0. {
+                        self.events.record_contact(i, ContactTarget::Constraint(ci), correction / depth, depth, depth * self.masses[i] / sub_dt);
+                        self.substep_contacts.push((i, ContactTarget::Constraint(ci), correction / depth, depth));
+                    }
+                    *pos = new_pos;
                 }
             }
         }
     }
-    pub fn apply_links(&mut self) {
-        // let mut removed_links = Vec::new();
-        for _ in 0..3 {
-            for i in (0..self.links.len()).rev() {
+    // displacement of a particle over the current substep, used to damp links
+    fn substep_displacement(&self, handle: usize, sub_dt: f32) -> Vec2 {
+        match self.integrator {
+            Integrator::SemiImplicitEuler | Integrator::VelocityVerlet => self.velocities[handle] * sub_dt,
+            Integrator::Verlet | Integrator::Xpbd => self.positions[handle] - self.positions_old[handle],
+        }
+    }
+    // links across a periodic seam are measured between the nearest images of their particles
+    pub fn apply_links(&mut self, sub_dt: f32) {
+        let periods = self.periods();
+        for i in (0..self.links.len()).rev() {
+            let (p1, p2) = self.links[i];
+            let dist = minimum_image(self.positions[p1] - self.positions[p2], periods).length();
+            let strain = (dist - self.link_dists[i]).abs() / self.link_dists[i].max(f32::EPSILON);
+            // hot links weaken, and melted ones break whatever their strain
+            let heat_factor = self.link_heat_factor(p1, p2);
+            if (strain > self.link_strengths[i] * heat_factor) || (heat_factor == 0.) {
+                self.events.link_breaks.push(LinkBreak {
+                    p1, p2, strain,
+                    position: self.positions[p2] + 0.5 * minimum_image(self.positions[p1] - self.positions[p2], periods),
+                });
+                self.events.total_link_breaks += 1;
+                self.remove_link(i);
+            }
+        }
+
+        // xpbd: lambda accumulates the constraint impulse over the iterations of one substep
+        for lambda in self.link_lambdas.iter_mut() {
+            *lambda = 0.;
+        }
+        self.reset_attachments();
+        for _ in 0..self.link_iterations {
+            self.solve_attachments(sub_dt);
+            for i in 0..self.links.len() {
                 let (p1, p2) = self.links[i];
-                let axis = self.positions[p1] - self.positions[p2];
+                let axis = minimum_image(self.positions[p1] - self.positions[p2], periods);
                 let dist = axis.length();
+                if dist == 0. {
+                    continue;
+                }
                 let n = axis / dist;
-                let mut delta = self.link_dists[i] - dist;
-                if delta > self.link_strengths[i] {
-                    delta = self.link_strengths[i];
-                    self.links.remove(i);
-                    self.link_dists.remove(i);
-                    self.link_strengths.remove(i);
+                let c = dist - self.link_dists[i];
+                let alpha = self.link_compliances[i] / (sub_dt * sub_dt);
+                let gamma = self.link_compliances[i] * self.link_dampings[i] / sub_dt;
+                let c_dot = n.dot(self.substep_displacement(p1, sub_dt) - self.substep_displacement(p2, sub_dt));
+                let (w1, w2) = (1. / self.masses[p1], 1. / self.masses[p2]);
+                if w1 + w2 == 0. {
+                    continue;
                 }
-                self.positions[p1] += 0.5 * delta * n;
-                self.positions[p2] += -0.5 * delta * n;
+                let d_lambda = (-c - alpha * self.link_lambdas[i] - gamma * c_dot) / ((1. + gamma) * (w1 + w2) + alpha);
+                self.link_lambdas[i] += d_lambda;
+                self.positions[p1] += w1 * d_lambda * n;
+                self.positions[p2] += -w2 * d_lambda * n;
             }
         }
     }
-    pub fn apply_collisions(&mut self) {
+    // `outside` flags the particles beyond the grid when they are left to `apply_unbounded_collisions`
+    pub fn apply_collisions(&mut self, outside: &[bool], sub_dt: f32) {
         let new_positions = Arc::new(Mutex::new(self.positions.clone()));
         let new_grid = Arc::new(Mutex::new(self.grid.clone()));
+        let contacts = Arc::new(Mutex::new(Vec::new()));
+        let ignored_pairs = &self.ignored_pairs;
+        let periods = self.periods();
         let n_threads = num_cpus::get();
         let cols_per = self.grid.width / n_threads;
 
@@ -262,25 +673,54 @@ impl Space {
                         }
                         for dx in -1..=1 {
                             for dy in -1..=1 {
-                                if (x as isize + dx < 0) || (x as isize + dx > self.grid.width as isize - 1) || (y as isize + dy < 0) || (y as isize + dy > self.grid.height as isize - 1) {
+                                let (mut nx, mut ny) = (x as isize + dx, y as isize + dy);
+                                // a wrapping world continues on the other side of the grid
+                                if periods.x > 0. {
+                                    nx = nx.rem_euclid(self.grid.width as isize);
+                                }
+                                if periods.y > 0. {
+                                    ny = ny.rem_euclid(self.grid.height as isize);
+                                }
+                                if (nx < 0) || (nx > self.grid.width as isize - 1) || (ny < 0) || (ny > self.grid.height as isize - 1) {
                                     continue;
                                 }
-                                let other = self.grid.get((x as isize + dx) as usize, (y as isize + dy) as usize).clone();
+                                let other = self.grid.get(nx as usize, ny as usize).clone();
                                 if other.is_empty() {
                                     continue;
                                 }
 
                                 for i in current_cell.iter() {
                                     for j in other.iter() {
+                                        if !outside.is_empty() && (outside[*i] || outside[*j]) {
+                                            continue;
+                                        }
+                                        // fluid pairs are kept apart by the density constraint instead
+                                        if (self.kinds[*i] == ParticleKind::Fluid) && (self.kinds[*j] == ParticleKind::Fluid) {
+                                            continue;
+                                        }
+                                        if !ignored_pairs.is_empty() && ignored_pairs.contains(&(*i.min(j), *i.max(j))) {
+                                            continue;
+                                        }
+                                        if !self.filters[*i].collides_with(&self.filters[*j]) {
+                                            continue;
+                                        }
                                         if *i != *j {
-                                            let collision_axis = thread_positions[*i] - thread_positions[*j];
+                                            let collision_axis = minimum_image(thread_positions[*i] - thread_positions[*j], periods);
                                             let center_dist = self.radii[*i] + self.radii[*j];
                                             let dist = collision_axis.length();
-                                            if dist < center_dist {
+                                            // clamping can stack particles exactly on top of each other, leaving no axis to push along
+                                            if (dist < center_dist) && (dist > 0.) {
                                                 let n = collision_axis / dist;
                                                 let delta = center_dist - dist;
-                                                thread_positions[*i] += 0.5 * delta * n;
-                                                thread_positions[*j] += -0.5 * delta * n;
+                                                let sensor = self.filters[*i].sensor || self.filters[*j].sensor;
+                                                contacts.lock().unwrap().push((*i, *j, n, delta, sensor));
+                                                // heavier particles give way less, in proportion to their inverse mass
+                                                let (w_i, w_j) = (1. / self.masses[*i], 1. / self.masses[*j]);
+                                                if sensor || (w_i + w_j == 0.) {
+                                                    continue;
+                                                }
+                                                thread_positions[*i] += w_i / (w_i + w_j) * delta * n;
+                                                thread_positions[*j] += -w_j / (w_i + w_j) * delta * n;
                                                 new_grid.lock().unwrap().update_obj(*i, thread_positions[*i]);
                                                 new_grid.lock().unwrap().update_obj(*j, thread_positions[*j]);
                                             }
@@ -296,6 +736,45 @@ impl Space {
 
         self.positions = new_positions.lock().unwrap().clone();
         self.grid = new_grid.lock().unwrap().clone();
+        for (i, j, n, delta, sensor) in contacts.lock().unwrap().iter() {
+            // sensor overlaps are reported without any impulse
+            if *sensor {
+                self.events.record_contact(*i, ContactTarget::Particle(*j), *n, *delta, 0.);
+                continue;
+            }
+            let impulse = delta / ((1. / self.masses[*i] + 1. / self.masses[*j]) * sub_dt);
+            self.events.record_contact(*i, ContactTarget::Particle(*j), *n, *delta, impulse);
+            self.substep_contacts.push((*i, ContactTarget::Particle(*j), *n, *delta));
+        }
+    }
+    // same response as `apply_collisions`, for a single pair
+    fn collide_pair(&mut self, i: usize, j: usize, sub_dt: f32) {
+        if (self.kinds[i] == ParticleKind::Fluid) && (self.kinds[j] == ParticleKind::Fluid) {
+            return;
+        }
+        if self.ignored_pairs.contains(&(i.min(j), i.max(j))) || !self.filters[i].collides_with(&self.filters[j]) {
+            return;
+        }
+        let collision_axis = minimum_image(self.positions[i] - self.positions[j], self.periods());
+        let center_dist = self.radii[i] + self.radii[j];
+        let dist = collision_axis.length();
+        if (dist >= center_dist) || (dist == 0.) {
+            return;
+        }
+        let n = collision_axis / dist;
+        let delta = center_dist - dist;
+        if self.filters[i].sensor || self.filters[j].sensor {
+            self.events.record_contact(i, ContactTarget::Particle(j), n, delta, 0.);
+            return;
+        }
+        let (w_i, w_j) = (1. / self.masses[i], 1. / self.masses[j]);
+        if w_i + w_j == 0. {
+            return;
+        }
+        self.positions[i] += w_i / (w_i + w_j) * delta * n;
+        self.positions[j] += -w_j / (w_i + w_j) * delta * n;
+        self.events.record_contact(i, ContactTarget::Particle(j), n, delta, delta / ((w_i + w_j) * sub_dt));
+        self.substep_contacts.push((i, ContactTarget::Particle(j), n, delta));
     }
     // pub fn apply_collisions(&mut self) {
     //     for x in 0..self.grid.width {
@@ -336,13 +815,17 @@ impl Space {
     //         }
     //     }
     // }
+    fn draw_position(&self, handle: usize) -> Vec2 {
+        self.positions_prev[handle].lerp(self.positions[handle], self.alpha)
+    }
     pub fn draw(&mut self) {
         let smaller_dim = screen_width().min(screen_height());
         let y_diff = screen_height() - smaller_dim;
         let x_diff = screen_width() - smaller_dim;
-        for (pos, radius, color) in izip!(self.positions.iter(), self.radii.iter(), self.colors.iter()) {
+        for (i, radius) in self.radii.iter().enumerate() {
+            let pos = self.draw_position(i);
             let projected = vec2(pos.x / 100. * smaller_dim, pos.y / 100. * smaller_dim);
-            draw_circle(projected.x + x_diff / 2., projected.y + y_diff / 2., *radius / 100. * smaller_dim, *color);
+            draw_circle(projected.x + x_diff / 2., projected.y + y_diff / 2., *radius / 100. * smaller_dim, self.thermal_color(i));
         }
     }
     pub fn draw_debug(&mut self) {
@@ -357,10 +840,62 @@ impl Space {
         for constraint in self.constraints.iter() {
             constraint.draw();
         }
+        for field in self.force_fields.iter().flatten() {
+            field.draw();
+        }
+        self.draw_regions();
+        let periods = self.periods();
         for (p1, p2) in self.links.iter() {
-            let pos1 = self.positions[*p1] / 100. * smaller_dim;
-            let pos2 = self.positions[*p2] / 100. * smaller_dim;
-            draw_line(pos1.x + x_diff / 2., pos1.y + y_diff / 2., pos2.x + x_diff / 2., pos2.y + y_diff / 2., 2., GRAY)
+            let (pos1, pos2) = (self.draw_position(*p1), self.draw_position(*p2));
+            // a link across a periodic seam is drawn as two stubs leaving opposite sides of the world
+            let axis = minimum_image(pos2 - pos1, periods);
+            for (from, to) in [(pos1, pos1 + axis), (pos2, pos2 - axis)] {
+                let from = from / 100. * smaller_dim;
+                let to = to / 100. * smaller_dim;
+                draw_line(from.x + x_diff / 2., from.y + y_diff / 2., to.x + x_diff / 2., to.y + y_diff / 2., 2., GRAY);
+                if axis == pos2 - pos1 {
+                    break;
+                }
+            }
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GRAVITY: Vec2 = Vec2::new(0., 30.);
+    const COMPLIANCE: f32 = 0.01;
+
+    // a bob hanging from an immovable particle by a soft spring, left to settle; returns how far the spring stretched.
+    // the scene sits by the origin, where f32 resolves the per-substep displacements of verlet and xpbd finely enough
+    fn spring_stretch(integrator: Integrator, substeps: usize) -> f32 {
+        let mut space = Space::new();
+        space.set_integrator(integrator);
+        space.set_gravity(GRAVITY);
+        space.set_substeps(substeps);
+        space.set_global_damping(2.);
+        let anchor = space.add_particle(vec2(1., 1.), 0.5);
+        let bob = space.add_particle(vec2(1., 6.), 0.5);
+        space.set_gravity_scale(anchor, 0.);
+        space.set_mass(anchor, f32::INFINITY);
+        space.add_spring(anchor, bob, COMPLIANCE, 0., f32::INFINITY);
+        for _ in 0..600 {
+            space.update(1. / 60.);
+        }
+        (space.get_position(bob) - space.get_position(anchor)).length() - 5.
+    }
+
+    #[test]
+    fn spring_stretch_does_not_depend_on_substeps() {
+        for integrator in [Integrator::Verlet, Integrator::SemiImplicitEuler, Integrator::VelocityVerlet, Integrator::Xpbd] {
+            let coarse = spring_stretch(integrator, 4);
+            let fine = spring_stretch(integrator, 16);
+            // hooke's law with the compliance as inverse stiffness
+            let expected = COMPLIANCE * GRAVITY.length();
+            assert!((coarse - fine).abs() < 0.05 * expected, "{:?} stretched {coarse} with 4 substeps, {fine} with 16", integrator);
+            assert!((fine - expected).abs() < 0.1 * expected, "{:?} stretched {fine}, expected {expected}", integrator);
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use macroquad::prelude::*;
+use super::{Space, ContactTarget, sample_gradient};
+
+
+const DEFAULT_AMBIENT: f32 = 20.;
+
+
+// heat flows through the contacts of each substep at `conductivity` per degree of difference, with mass as
+// the heat capacity, and every particle relaxes towards `ambient` at rate `cooling`. links get weaker from
+// `weaken_temperature` on and melt at `melt_temperature`; with a `color_range` (cold, hot) particles are
+// drawn tinted blue below ambient and glowing above it
+#[derive(Clone, Copy, Debug)]
+pub struct ThermalParams {
+    pub conductivity: f32,
+    pub ambient: f32,
+    pub cooling: f32,
+    pub weaken_temperature: f32,
+    pub melt_temperature: f32,
+    pub color_range: Option<(f32, f32)>,
+}
+
+impl Default for ThermalParams {
+    fn default() -> Self {
+        Self {
+            conductivity: 5.,
+            ambient: DEFAULT_AMBIENT,
+            cooling: 0.05,
+            weaken_temperature: 400.,
+            melt_temperature: 800.,
+            color_range: Some((-50., 1000.)),
+        }
+    }
+}
+
+
+impl Space {
+    pub fn set_thermal(&mut self, thermal: Option<ThermalParams>) {
+        self.thermal = thermal;
+    }
+    pub fn set_temperature(&mut self, handle: usize, temperature: f32) {
+        self.temperatures[handle] = temperature;
+    }
+    pub fn get_temperature(&self, handle: usize) -> f32 {
+        self.temperatures[handle]
+    }
+    // a constraint with a temperature heats or cools the particles touching it; `None` makes it insulating
+    pub fn set_constraint_temperature(&mut self, constraint: usize, temperature: Option<f32>) {
+        self.constraint_temperatures[constraint] = temperature;
+    }
+    pub(super) fn ambient_temperature(&self) -> f32 {
+        self.thermal.map_or(DEFAULT_AMBIENT, |thermal| thermal.ambient)
+    }
+
+    pub(super) fn apply_heat_transfer(&mut self, sub_dt: f32) {
+        let Some(params) = self.thermal else {
+            return;
+        };
+        for k in 0..self.substep_contacts.len() {
+            let (i, other, _, _) = self.substep_contacts[k];
+            let w_i = 1. / self.masses[i];
+            let (difference, w_other) = match other {
+                ContactTarget::Particle(j) => (self.temperatures[j] - self.temperatures[i], 1. / self.masses[j]),
+                ContactTarget::Constraint(c) => match self.constraint_temperatures[c] {
+                    Some(temperature) => (temperature - self.temperatures[i], 0.),
+                    None => continue,
+                },
+            };
+            // never more than it takes to even the two temperatures out
+            let heat = (params.conductivity * sub_dt).min(1. / (w_i + w_other)) * difference;
+            self.temperatures[i] += heat * w_i;
+            if let ContactTarget::Particle(j) = other {
+                self.temperatures[j] -= heat * w_other;
+            }
+        }
+        let relax = 1. - (-params.cooling * sub_dt).exp();
+        for temperature in self.temperatures.iter_mut() {
+            *temperature += (params.ambient - *temperature) * relax;
+        }
+    }
+    // fraction of its strength a link keeps at the mean temperature of its particles
+    pub(super) fn link_heat_factor(&self, p1: usize, p2: usize) -> f32 {
+        let Some(params) = self.thermal else {
+            return 1.;
+        };
+        let temperature = 0.5 * (self.temperatures[p1] + self.temperatures[p2]);
+        if temperature >= params.melt_temperature {
+            return 0.;
+        }
+        (1. - (temperature - params.weaken_temperature) / (params.melt_temperature - params.weaken_temperature)).clamp(0., 1.)
+    }
+    pub(super) fn thermal_color(&self, handle: usize) -> Color {
+        let color = self.colors[handle];
+        let Some((params, (cold, hot))) = self.thermal.and_then(|params| Some((params, params.color_range?))) else {
+            return color;
+        };
+        let temperature = self.temperatures[handle];
+        if temperature < params.ambient {
+            let t = (params.ambient - temperature) / (params.ambient - cold).max(f32::EPSILON);
+            return sample_gradient(&[color, Color::new(0.2, 0.4, 1.0, color.a)], t);
+        }
+        let t = (temperature - params.ambient) / (hot - params.ambient).max(f32::EPSILON);
+        sample_gradient(&[color, Color::new(0.8, 0.1, 0.05, color.a), ORANGE, YELLOW, WHITE], t)
+    }
+}
@@ -0,0 +1,43 @@
+use std::any::Any;
+use super::Space;
+
+
+// one optional value of any type per particle; it moves with the particle when handles shift after a removal
+pub type UserData = Box<dyn Any + Send + Sync>;
+
+
+impl Space {
+    pub fn set_user_data<T: Any + Send + Sync>(&mut self, handle: usize, data: T) {
+        self.user_data[handle] = Some(Box::new(data));
+    }
+    // `None` when the particle has no data or data of another type
+    pub fn get_user_data<T: Any>(&self, handle: usize) -> Option<&T> {
+        self.user_data[handle].as_ref().and_then(|data| data.downcast_ref())
+    }
+    pub fn get_user_data_mut<T: Any>(&mut self, handle: usize) -> Option<&mut T> {
+        self.user_data[handle].as_mut().and_then(|data| data.downcast_mut())
+    }
+    // data of another type is left in place
+    pub fn take_user_data<T: Any>(&mut self, handle: usize) -> Option<T> {
+        match self.user_data[handle].take()?.downcast() {
+            Ok(data) => Some(*data),
+            Err(data) => {
+                self.user_data[handle] = Some(data);
+                None
+            },
+        }
+    }
+    pub fn clear_user_data(&mut self, handle: usize) {
+        self.user_data[handle] = None;
+    }
+    // data of the particles removed during the last update, with the handles they had before their removal
+    pub fn drain_removed_user_data(&mut self) -> Vec<(usize, UserData)> {
+        std::mem::take(&mut self.events.removed_user_data)
+    }
+    // particles carrying data of type `T`, with their handles
+    pub fn find_user_data<T: Any>(&self) -> Vec<(usize, &T)> {
+        self.user_data.iter().enumerate()
+            .filter_map(|(i, data)| Some((i, data.as_ref()?.downcast_ref()?)))
+            .collect()
+    }
+}